@@ -0,0 +1,450 @@
+use crate::helper::Vector;
+use crate::random::{random_seed, Rng};
+use crate::snake::Direction;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+/// Everything a computer player is allowed to look at.
+pub struct GameView<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub snake: &'a VecDeque<Vector>,
+    /// Food worth eating, which leaves out poison.
+    pub foods: Vec<Vector>,
+    pub direction: Direction,
+    /// Bodies of the other snakes still alive.
+    pub others: Vec<&'a VecDeque<Vector>>,
+    /// Cells of every portal. Computer players keep clear of them.
+    pub portals: Vec<Vector>,
+    /// Cells of the hazards that would kill the snake, and of poison.
+    pub hazards: Vec<Vector>,
+}
+
+impl GameView<'_> {
+    fn index(&self, pos: &Vector) -> Option<usize> {
+        pos.index_in(self.width, self.height)
+    }
+
+    fn neighbours(&self, pos: Vector) -> impl Iterator<Item = Vector> + '_ {
+        Direction::ALL
+            .into_iter()
+            .map(move |direction| &pos + direction.to_vec())
+            .filter(|next| self.index(next).is_some())
+    }
+
+    /// A grid of the cells occupied by `body`.
+    fn occupied<'b>(&self, body: impl IntoIterator<Item = &'b Vector>) -> Vec<bool> {
+        let mut grid = vec![false; self.width * self.height];
+        for pos in body {
+            if let Some(i) = self.index(pos) {
+                grid[i] = true;
+            }
+        }
+        grid
+    }
+
+    /// A grid of the cells occupied by `body`, any other snake, a portal or
+    /// a hazard.
+    fn obstacles<'b>(&self, body: impl IntoIterator<Item = &'b Vector>) -> Vec<bool> {
+        let mut grid = self.occupied(body);
+        for pos in self
+            .others
+            .iter()
+            .flat_map(|other| other.iter())
+            .chain(&self.portals)
+            .chain(&self.hazards)
+        {
+            if let Some(i) = self.index(pos) {
+                grid[i] = true;
+            }
+        }
+        grid
+    }
+
+    /// Marks every cell another snake's head could move into next tick.
+    fn block_other_heads(&self, grid: &mut [bool]) {
+        for other in &self.others {
+            for next in self.neighbours(other[0]) {
+                grid[self.index(&next).unwrap()] = true;
+            }
+        }
+    }
+
+    /// Shortest path from `start` to the closest cell satisfying `is_goal`,
+    /// not including `start` itself.
+    fn bfs(
+        &self,
+        start: Vector,
+        blocked: &[bool],
+        is_goal: impl Fn(&Vector) -> bool,
+    ) -> Option<Vec<Vector>> {
+        let mut came_from = vec![None; self.width * self.height];
+        let mut queue = VecDeque::from([start]);
+        while let Some(pos) = queue.pop_front() {
+            if pos != start && is_goal(&pos) {
+                return Some(self.path(&came_from, start, pos));
+            }
+            for next in self.neighbours(pos) {
+                let i = self.index(&next).unwrap();
+                if blocked[i] || came_from[i].is_some() || next == start {
+                    continue;
+                }
+                came_from[i] = Some(pos);
+                queue.push_back(next);
+            }
+        }
+        None
+    }
+
+    /// Shortest path from `start` to the closest of `goals`, using the
+    /// manhattan distance to guide the search.
+    fn astar(&self, start: Vector, blocked: &[bool], goals: &[Vector]) -> Option<Vec<Vector>> {
+        let heuristic = |pos: &Vector| {
+            goals
+                .iter()
+                .map(|goal| (goal.0 - pos.0).unsigned_abs() + (goal.1 - pos.1).unsigned_abs())
+                .min()
+                .unwrap_or(0)
+        };
+        let mut came_from = vec![None; self.width * self.height];
+        let mut cost = vec![usize::MAX; self.width * self.height];
+        let mut open = BinaryHeap::new();
+        cost[self.index(&start)?] = 0;
+        open.push(Reverse((heuristic(&start), 0, start.0, start.1)));
+        while let Some(Reverse((_, g, x, y))) = open.pop() {
+            let pos = Vector(x, y);
+            if g > cost[self.index(&pos).unwrap()] {
+                continue;
+            }
+            if pos != start && goals.contains(&pos) {
+                return Some(self.path(&came_from, start, pos));
+            }
+            for next in self.neighbours(pos) {
+                let i = self.index(&next).unwrap();
+                if blocked[i] || g + 1 >= cost[i] {
+                    continue;
+                }
+                cost[i] = g + 1;
+                came_from[i] = Some(pos);
+                open.push(Reverse((g + 1 + heuristic(&next), g + 1, next.0, next.1)));
+            }
+        }
+        None
+    }
+
+    fn path(&self, came_from: &[Option<Vector>], start: Vector, end: Vector) -> Vec<Vector> {
+        let mut path = vec![end];
+        let mut pos = end;
+        while let Some(prev) = came_from[self.index(&pos).unwrap()] {
+            if prev == start {
+                break;
+            }
+            path.push(prev);
+            pos = prev;
+        }
+        path.reverse();
+        path
+    }
+
+    /// Number of free cells reachable from `start`.
+    fn space(&self, start: Vector, blocked: &[bool]) -> usize {
+        let mut seen = blocked.to_vec();
+        if let Some(i) = self.index(&start) {
+            seen[i] = true;
+        }
+        let mut queue = VecDeque::from([start]);
+        let mut count = 0;
+        while let Some(pos) = queue.pop_front() {
+            for next in self.neighbours(pos) {
+                let i = self.index(&next).unwrap();
+                if !seen[i] {
+                    seen[i] = true;
+                    count += 1;
+                    queue.push_back(next);
+                }
+            }
+        }
+        count
+    }
+
+    /// The free neighbouring cell with the most room behind it, or straight
+    /// ahead if every move is fatal.
+    fn roomiest_move(&self, blocked: &[bool]) -> Direction {
+        let head = self.snake[0];
+        Direction::ALL
+            .into_iter()
+            .filter(|direction| *direction != self.direction.opposite())
+            .filter_map(|direction| {
+                let next = &head + direction.to_vec();
+                let i = self.index(&next)?;
+                (!blocked[i]).then(|| (direction, self.space(next, blocked)))
+            })
+            .max_by_key(|(_, space)| *space)
+            .map_or(self.direction, |(direction, _)| direction)
+    }
+
+    fn first_step(&self, path: &[Vector]) -> Option<Direction> {
+        Direction::between(&self.snake[0], path.first()?)
+    }
+}
+
+pub trait Strategy {
+    fn next_direction(&mut self, view: &GameView) -> Direction;
+}
+
+/// Heads for the nearest food by breadth-first search, without looking
+/// further ahead than that.
+pub struct Greedy;
+
+impl Strategy for Greedy {
+    fn next_direction(&mut self, view: &GameView) -> Direction {
+        let blocked = view.obstacles(view.snake);
+        let foods = view.occupied(&view.foods);
+        view.bfs(view.snake[0], &blocked, |pos| {
+            view.index(pos).is_some_and(|i| foods[i])
+        })
+        .and_then(|path| view.first_step(&path))
+        .unwrap_or_else(|| view.roomiest_move(&blocked))
+    }
+}
+
+/// Takes the A* path to the nearest food only if the snake could still reach
+/// its own tail after eating it, and otherwise chases its tail until it can.
+/// Stays clear of cells other snakes could move into whenever possible.
+pub struct SafeAStar;
+
+impl SafeAStar {
+    /// Whether, after following `path`, the snake can still get back to
+    /// its tail.
+    fn is_safe(view: &GameView, path: &[Vector]) -> bool {
+        let mut body = view.snake.clone();
+        for pos in path {
+            body.push_front(*pos);
+            if !view.foods.contains(pos) {
+                body.pop_back();
+            }
+        }
+        if body.len() < 3 {
+            return true;
+        }
+        let tail = *body.back().unwrap();
+        let blocked = view.obstacles(&body);
+        view.bfs(body[0], &blocked, |pos| {
+            Direction::between(pos, &tail).is_some()
+        })
+        .is_some()
+    }
+}
+
+impl Strategy for SafeAStar {
+    fn next_direction(&mut self, view: &GameView) -> Direction {
+        let head = view.snake[0];
+        let mut blocked = view.obstacles(view.snake);
+        view.block_other_heads(&mut blocked);
+        if view
+            .neighbours(head)
+            .all(|next| blocked[view.index(&next).unwrap()])
+        {
+            // Every move is next to another head, so risk it.
+            blocked = view.obstacles(view.snake);
+        }
+        if let Some(path) = view.astar(head, &blocked, &view.foods) {
+            if Self::is_safe(view, &path) {
+                if let Some(direction) = view.first_step(&path) {
+                    return direction;
+                }
+            }
+        }
+        let tail = *view.snake.back().unwrap();
+        if view.snake.len() > 2 {
+            let to_tail = view.bfs(head, &blocked, |pos| {
+                Direction::between(pos, &tail).is_some()
+            });
+            if let Some(direction) = to_tail.and_then(|path| view.first_step(&path)) {
+                return direction;
+            }
+        }
+        view.roomiest_move(&blocked)
+    }
+}
+
+/// Mostly wanders around at random, only sometimes going for the nearest
+/// food, and never takes a move that is fatal right away if it has a choice.
+pub struct Wanderer {
+    rng: Rng,
+}
+
+impl Wanderer {
+    /// A wanderer whose moves follow from `seed`, so a seeded game against it
+    /// plays out the same every time.
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Default for Wanderer {
+    fn default() -> Self {
+        Self::with_seed(random_seed())
+    }
+}
+
+impl Strategy for Wanderer {
+    fn next_direction(&mut self, view: &GameView) -> Direction {
+        if self.rng.range(0, 3) == 0 {
+            return Greedy.next_direction(view);
+        }
+        let blocked = view.obstacles(view.snake);
+        let safe = Direction::ALL
+            .into_iter()
+            .filter(|direction| *direction != view.direction.opposite())
+            .filter(|direction| {
+                view.index(&(&view.snake[0] + direction.to_vec()))
+                    .is_some_and(|i| !blocked[i])
+            })
+            .collect::<Vec<_>>();
+        if safe.contains(&view.direction) && self.rng.range(0, 4) != 0 {
+            return view.direction;
+        }
+        match safe.len() {
+            0 => view.direction,
+            len => safe[self.rng.range(0, len)],
+        }
+    }
+}
+
+/// Follows a fixed cycle through every cell of the board, which is slow but
+/// never dies until the board is full. Needs an even width or height and
+/// falls back to [`Greedy`] otherwise.
+#[derive(Default)]
+pub struct Hamiltonian {
+    size: (usize, usize),
+    next: Option<Vec<Direction>>,
+}
+
+impl Hamiltonian {
+    fn cycle(width: usize, height: usize) -> Option<Vec<Direction>> {
+        if width < 2 || height < 2 || (width % 2 == 1 && height % 2 == 1) {
+            return None;
+        }
+        // Build the cycle on a board with an even number of rows, transposing
+        // if only the width is even.
+        let transpose = height % 2 == 1;
+        let (columns, rows) = if transpose {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        let mut order = (0..columns).map(|x| (x, 0)).collect::<Vec<_>>();
+        for y in 1..rows {
+            if y % 2 == 1 {
+                order.extend((1..columns).rev().map(|x| (x, y)));
+            } else {
+                order.extend((1..columns).map(|x| (x, y)));
+            }
+        }
+        order.extend((1..rows).rev().map(|y| (0, y)));
+
+        let to_vector = |(x, y): (usize, usize)| {
+            if transpose {
+                Vector(y as isize, x as isize)
+            } else {
+                Vector(x as isize, y as isize)
+            }
+        };
+        let mut next = vec![Direction::Up; width * height];
+        for (i, cell) in order.iter().enumerate() {
+            let from = to_vector(*cell);
+            let to = to_vector(order[(i + 1) % order.len()]);
+            next[from.1 as usize * width + from.0 as usize] = Direction::between(&from, &to)?;
+        }
+        Some(next)
+    }
+}
+
+impl Strategy for Hamiltonian {
+    fn next_direction(&mut self, view: &GameView) -> Direction {
+        if self.size != (view.width, view.height) {
+            self.size = (view.width, view.height);
+            self.next = Self::cycle(view.width, view.height);
+        }
+        match (&self.next, view.index(&view.snake[0])) {
+            (Some(next), Some(i)) => next[i],
+            _ => Greedy.next_direction(view),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrategyKind {
+    Wanderer,
+    Greedy,
+    SafeAStar,
+    Hamiltonian,
+}
+
+impl StrategyKind {
+    pub const ALL: [StrategyKind; 4] = [
+        StrategyKind::Wanderer,
+        StrategyKind::Greedy,
+        StrategyKind::SafeAStar,
+        StrategyKind::Hamiltonian,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StrategyKind::Wanderer => "random",
+            StrategyKind::Greedy => "greedy",
+            StrategyKind::SafeAStar => "astar",
+            StrategyKind::Hamiltonian => "hamiltonian",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<StrategyKind> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    pub fn build(self) -> Box<dyn Strategy> {
+        self.build_with_seed(random_seed())
+    }
+
+    /// Builds the strategy with any randomness it has drawn from `seed`.
+    pub fn build_with_seed(self, seed: u64) -> Box<dyn Strategy> {
+        match self {
+            StrategyKind::Wanderer => Box::new(Wanderer::with_seed(seed)),
+            StrategyKind::Greedy => Box::new(Greedy),
+            StrategyKind::SafeAStar => Box::new(SafeAStar),
+            StrategyKind::Hamiltonian => Box::new(Hamiltonian::default()),
+        }
+    }
+}
+
+/// How hard the computer opponents play.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    pub fn strategy(self) -> StrategyKind {
+        match self {
+            Difficulty::Easy => StrategyKind::Wanderer,
+            Difficulty::Normal => StrategyKind::Greedy,
+            Difficulty::Hard => StrategyKind::SafeAStar,
+        }
+    }
+}
@@ -0,0 +1,462 @@
+use crate::helper::{onclick, style};
+use crate::snake::{FoodKind, GameEvent};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use web_sys::{
+    window, AudioBuffer, AudioContext, AudioContextState, GainNode, HtmlButtonElement, HtmlElement,
+    HtmlInputElement, OscillatorType,
+};
+
+/// The music's tune, one pitch in Hz for every eighth note, looped.
+const MELODY: [f32; 16] = [
+    440.0, 523.25, 659.25, 523.25, 587.33, 698.46, 659.25, 523.25, 392.0, 493.88, 587.33, 493.88,
+    440.0, 523.25, 659.25, 880.0,
+];
+/// The bass under the tune, one pitch for every four of its notes.
+const BASS: [f32; 4] = [110.0, 146.83, 98.0, 110.0];
+/// Seconds every note of the tune lasts.
+const NOTE_LENGTH: f32 = 0.25;
+/// Most ticks between two foods for the second to carry on a combo.
+const COMBO_WINDOW: usize = 8;
+/// Combo length after which eating stops getting any higher.
+const MAX_COMBO: usize = 12;
+
+/// Volumes from 0 to 1. The effects and music volumes are scaled by the
+/// master volume.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: f64,
+    pub effects: f64,
+    pub music: f64,
+    pub muted: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            effects: 1.0,
+            music: 0.5,
+            muted: false,
+        }
+    }
+}
+
+impl AudioSettings {
+    fn effects_volume(&self) -> f64 {
+        if self.muted {
+            0.0
+        } else {
+            self.master * self.effects
+        }
+    }
+
+    fn music_volume(&self) -> f64 {
+        if self.muted {
+            0.0
+        } else {
+            self.master * self.music
+        }
+    }
+}
+
+/// A sound effect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sound {
+    /// `combo` is the number of foods eaten in quick succession before this
+    /// one.
+    Eat {
+        kind: FoodKind,
+        combo: usize,
+    },
+    PowerUp,
+    Devour,
+    Poison,
+    Shed,
+    Turn,
+    Cut,
+    Death,
+    TimeUp,
+    LevelUp,
+    HighScore,
+    FilledBoard,
+}
+
+/// One oscillator of an effect, gliding from one pitch to another while it
+/// fades out.
+struct Tone {
+    wave: OscillatorType,
+    /// Pitches in Hz.
+    from: f32,
+    to: f32,
+    /// Seconds from the start of the effect.
+    delay: f64,
+    length: f64,
+    /// Loudness relative to the effects volume.
+    gain: f32,
+}
+
+impl Tone {
+    fn new(wave: OscillatorType, from: f32, to: f32, length: f64, gain: f32) -> Self {
+        Self {
+            wave,
+            from,
+            to,
+            delay: 0.0,
+            length,
+            gain,
+        }
+    }
+
+    /// Notes of the same length played one after the other.
+    fn arpeggio(wave: OscillatorType, notes: &[f32], length: f64, gain: f32) -> Vec<Self> {
+        notes
+            .iter()
+            .enumerate()
+            .map(|(i, &note)| Self {
+                delay: i as f64 * length * 0.75,
+                ..Self::new(wave, note, note, length, gain)
+            })
+            .collect()
+    }
+}
+
+impl Sound {
+    fn tones(self) -> Vec<Tone> {
+        match self {
+            Sound::Eat { kind, combo } => {
+                let (wave, pitch) = match kind {
+                    FoodKind::Carrot => (OscillatorType::Square, 440.0),
+                    FoodKind::Bread => (OscillatorType::Triangle, 330.0),
+                    FoodKind::Avocado => (OscillatorType::Sine, 523.25),
+                    FoodKind::Cherry => (OscillatorType::Square, 659.25),
+                    FoodKind::Rabbit => (OscillatorType::Sawtooth, 587.33),
+                    FoodKind::Bug => (OscillatorType::Sawtooth, 392.0),
+                    FoodKind::Poison => (OscillatorType::Sawtooth, 110.0),
+                };
+                // A semitone higher for every food of the combo.
+                let pitch = pitch * 2f32.powf(combo.min(MAX_COMBO) as f32 / 12.0);
+                vec![Tone::new(wave, pitch, pitch * 1.5, 0.12, 0.3)]
+            }
+            Sound::PowerUp => vec![Tone::new(OscillatorType::Square, 330.0, 1320.0, 0.3, 0.25)],
+            Sound::Devour => vec![Tone::new(OscillatorType::Sawtooth, 220.0, 880.0, 0.15, 0.3)],
+            Sound::Poison => vec![Tone::new(OscillatorType::Sawtooth, 330.0, 82.5, 0.4, 0.3)],
+            Sound::Shed => vec![Tone::new(OscillatorType::Sine, 440.0, 330.0, 0.05, 0.15)],
+            Sound::Turn => vec![Tone::new(OscillatorType::Sine, 880.0, 660.0, 0.03, 0.1)],
+            Sound::Cut => vec![Tone::new(OscillatorType::Triangle, 660.0, 220.0, 0.2, 0.4)],
+            Sound::Death => vec![
+                Tone::new(OscillatorType::Sawtooth, 440.0, 55.0, 0.8, 0.3),
+                Tone::new(OscillatorType::Square, 220.0, 40.0, 0.8, 0.15),
+            ],
+            Sound::TimeUp => {
+                Tone::arpeggio(OscillatorType::Square, &[880.0, 880.0, 440.0], 0.2, 0.3)
+            }
+            Sound::LevelUp => Tone::arpeggio(
+                OscillatorType::Triangle,
+                &[523.25, 659.25, 783.99],
+                0.1,
+                0.4,
+            ),
+            Sound::HighScore => Tone::arpeggio(
+                OscillatorType::Square,
+                &[523.25, 659.25, 783.99, 1046.5],
+                0.12,
+                0.25,
+            ),
+            Sound::FilledBoard => Tone::arpeggio(
+                OscillatorType::Triangle,
+                &[523.25, 659.25, 783.99, 1046.5, 1318.5, 1568.0],
+                0.15,
+                0.4,
+            ),
+        }
+    }
+}
+
+struct Audio {
+    settings: AudioSettings,
+    /// Effects are synthesized as they are played, and the music once at the
+    /// start, so there is nothing to download.
+    context: AudioContext,
+    /// The music plays through this, at the music volume.
+    music: GainNode,
+    /// Length of each player's current combo and the tick it was last
+    /// extended on.
+    combos: HashMap<usize, (usize, usize)>,
+}
+
+thread_local! {
+    static AUDIO: RefCell<Option<Audio>> = const { RefCell::new(None) };
+    static IGNORE_REJECTION: Closure<dyn FnMut(JsValue)> =
+        Closure::wrap(Box::new(|_| {}) as Box<dyn FnMut(JsValue)>);
+}
+
+fn with_audio(f: impl FnOnce(&mut Audio)) {
+    AUDIO.with(|audio| {
+        if let Some(audio) = &mut *audio.borrow_mut() {
+            f(audio);
+        }
+    });
+}
+
+/// Ignores the rejection of a promise to start playing. Browsers refuse to
+/// play anything before the page has been interacted with, which isn't worth
+/// stopping the game for.
+fn ignore_rejection(promise: Result<js_sys::Promise, JsValue>) {
+    if let Ok(promise) = promise {
+        IGNORE_REJECTION.with(|ignore| {
+            let _ = promise.catch(ignore);
+        });
+    }
+}
+
+fn resume() {
+    with_audio(|audio| {
+        if audio.context.state() == AudioContextState::Suspended {
+            ignore_rejection(audio.context.resume());
+        }
+    });
+}
+
+/// One loop of the music: a square wave tune over a triangle wave bass,
+/// every note fading out until the next.
+fn music_buffer(context: &AudioContext) -> Result<AudioBuffer, JsValue> {
+    let rate = context.sample_rate();
+    let note_samples = (NOTE_LENGTH * rate) as usize;
+    let samples = (0..note_samples * MELODY.len())
+        .map(|i| {
+            let time = i as f32 / rate;
+            let note = i / note_samples;
+            let since_note = (i % note_samples) as f32 / rate;
+            let since_bass = (i % (note_samples * 4)) as f32 / rate;
+            // A few milliseconds of attack keep the notes from clicking.
+            let fade = |since: f32, length: f32| (since / 0.005).min(1.0) * (1.0 - since / length);
+            let phase = (time * MELODY[note]).fract();
+            let square = if phase < 0.5 { 1.0 } else { -1.0 };
+            let phase = (time * BASS[note / 4]).fract();
+            let triangle = 4.0 * (phase - 0.5).abs() - 1.0;
+            0.15 * square * fade(since_note, NOTE_LENGTH)
+                + 0.3 * triangle * fade(since_bass, NOTE_LENGTH * 4.0)
+        })
+        .collect::<Vec<_>>();
+    let buffer = context.create_buffer(1, samples.len() as u32, rate)?;
+    buffer.copy_to_channel(&samples, 0)?;
+    Ok(buffer)
+}
+
+/// Starts the music looping through a node of its own, so its volume can be
+/// set apart from the effects'.
+fn start_music(context: &AudioContext, volume: f64) -> Result<GainNode, JsValue> {
+    let music = context.create_gain()?;
+    music.gain().set_value(volume as f32);
+    music.connect_with_audio_node(&context.destination())?;
+    let source = context.create_buffer_source()?;
+    source.set_buffer(Some(&music_buffer(context)?));
+    source.set_loop(true);
+    source.connect_with_audio_node(&music)?;
+    source.start()?;
+    Ok(music)
+}
+
+/// Sets up the sound with `settings`. Nothing is heard before the first key
+/// press or tap, since it wouldn't be allowed to play any earlier. Browsers
+/// without Web Audio get no sound, but the game works all the same.
+pub fn init_audio(settings: AudioSettings) {
+    let Ok(context) = AudioContext::new() else {
+        return;
+    };
+    let Ok(music) = start_music(&context, settings.music_volume()) else {
+        return;
+    };
+    AUDIO.with(|audio| {
+        *audio.borrow_mut() = Some(Audio {
+            settings,
+            context,
+            music,
+            combos: HashMap::new(),
+        })
+    });
+
+    let document = window().unwrap_throw().document().unwrap_throw();
+    let oninteraction = Closure::wrap(Box::new(resume) as Box<dyn FnMut()>);
+    for event in ["keydown", "pointerdown"] {
+        document
+            .add_event_listener_with_callback(event, oninteraction.as_ref().unchecked_ref())
+            .unwrap_throw();
+    }
+    oninteraction.forget();
+}
+
+fn play_tone(context: &AudioContext, tone: &Tone, volume: f32) -> Result<(), JsValue> {
+    let start = context.current_time() + tone.delay;
+    let end = start + tone.length;
+    let oscillator = context.create_oscillator()?;
+    oscillator.set_type(tone.wave);
+    let frequency = oscillator.frequency();
+    frequency.set_value_at_time(tone.from, start)?;
+    frequency.exponential_ramp_to_value_at_time(tone.to, end)?;
+    // A quick attack so the tone doesn't click, then an exponential decay,
+    // which can't reach zero itself.
+    let envelope = context.create_gain()?;
+    let gain = envelope.gain();
+    gain.set_value_at_time(0.0, start)?;
+    gain.linear_ramp_to_value_at_time(volume * tone.gain, start + 0.005)?;
+    gain.exponential_ramp_to_value_at_time(0.0001, end)?;
+    oscillator.connect_with_audio_node(&envelope)?;
+    envelope.connect_with_audio_node(&context.destination())?;
+    oscillator.start_with_when(start)?;
+    oscillator.stop_with_when(end)
+}
+
+pub fn play_sound(sound: Sound) {
+    with_audio(|audio| {
+        let volume = audio.settings.effects_volume() as f32;
+        if volume <= 0.0 {
+            return;
+        }
+        for tone in sound.tones() {
+            let _ = play_tone(&audio.context, &tone, volume);
+        }
+    });
+}
+
+/// Plays the sound of `event`, which happened in the tick numbered `tick`.
+pub fn play_event(event: &GameEvent, tick: usize) {
+    let sound = match *event {
+        GameEvent::Turned { .. } => Sound::Turn,
+        GameEvent::Ate { player, kind } => {
+            let mut combo = 0;
+            with_audio(|audio| {
+                let (length, last) = audio.combos.entry(player).or_insert((0, 0));
+                // Ticks start over with every game, which ends the combo too.
+                let quick = tick
+                    .checked_sub(*last)
+                    .is_some_and(|gap| gap <= COMBO_WINDOW);
+                *length = if quick && *length > 0 { *length + 1 } else { 1 };
+                *last = tick;
+                combo = *length - 1;
+            });
+            Sound::Eat { kind, combo }
+        }
+        GameEvent::Cut { .. } => Sound::Cut,
+        GameEvent::Died { .. } => Sound::Death,
+        GameEvent::FilledBoard { .. } => Sound::FilledBoard,
+        GameEvent::TimeUp => Sound::TimeUp,
+        GameEvent::PoweredUp { .. } => Sound::PowerUp,
+        GameEvent::Devoured { .. } => Sound::Devour,
+        GameEvent::Poisoned { .. } => Sound::Poison,
+        GameEvent::Shed { .. } => Sound::Shed,
+    };
+    play_sound(sound);
+}
+
+fn apply_settings(settings: AudioSettings) {
+    with_audio(|audio| {
+        audio.settings = settings;
+        audio.music.gain().set_value(settings.music_volume() as f32);
+    });
+    resume();
+}
+
+/// Shows volume sliders and a mute switch. Every change is applied right
+/// away and handed to `onchange` to be saved.
+pub fn show_sound_settings(onchange: impl FnMut(AudioSettings) + 'static) {
+    let Some(settings) = AUDIO.with(|audio| audio.borrow().as_ref().map(|audio| audio.settings))
+    else {
+        return;
+    };
+    let window = window().unwrap_throw();
+    let document = window.document().unwrap_throw();
+    if let Some(screen) = document.get_element_by_id("sound-settings") {
+        screen.remove();
+    }
+    let screen = document
+        .create_element("div")
+        .unwrap_throw()
+        .dyn_into::<HtmlElement>()
+        .unwrap_throw();
+    screen.set_id("sound-settings");
+    style!(screen {
+        "position": "fixed";
+        "top": "50%";
+        "left": "50%";
+        "transform": "translate(-50%, -50%)";
+        "padding": "16px 24px";
+        "background": "white";
+        "border": "1px solid black";
+        "font-family": "Roboto, sans serif";
+        "display": "grid";
+        "grid-template-columns": "auto auto";
+        "gap": "8px 16px";
+        "align-items": "center";
+    });
+    let input = |label: &str, kind: &str| {
+        let name = document.create_element("span").unwrap_throw();
+        name.set_text_content(Some(label));
+        let input = document
+            .create_element("input")
+            .unwrap_throw()
+            .dyn_into::<HtmlInputElement>()
+            .unwrap_throw();
+        input.set_type(kind);
+        screen.append_child(&name).unwrap_throw();
+        screen.append_child(&input).unwrap_throw();
+        input
+    };
+    let slider = |label: &str, value: f64| {
+        let slider = input(label, "range");
+        slider.set_min("0");
+        slider.set_max("100");
+        slider.set_value_as_number((value * 100.0).round());
+        slider
+    };
+    let master = slider("master", settings.master);
+    let effects = slider("effects", settings.effects);
+    let music = slider("music", settings.music);
+    let muted = input("mute", "checkbox");
+    muted.set_checked(settings.muted);
+    let close = document
+        .create_element("button")
+        .unwrap_throw()
+        .dyn_into::<HtmlButtonElement>()
+        .unwrap_throw();
+    close.set_text_content(Some("close"));
+    screen.append_child(&close).unwrap_throw();
+
+    let onchange = Rc::new(RefCell::new(onchange));
+    let oninput = Closure::wrap(Box::new({
+        let (master, effects, music, muted) = (
+            master.clone(),
+            effects.clone(),
+            music.clone(),
+            muted.clone(),
+        );
+        move || {
+            let settings = AudioSettings {
+                master: master.value_as_number() / 100.0,
+                effects: effects.value_as_number() / 100.0,
+                music: music.value_as_number() / 100.0,
+                muted: muted.checked(),
+            };
+            apply_settings(settings);
+            (onchange.borrow_mut())(settings);
+        }
+    }) as Box<dyn FnMut()>);
+    for input in [&master, &effects, &music, &muted] {
+        input.set_oninput(oninput.as_ref().dyn_ref());
+    }
+    oninput.forget();
+    onclick!(close -> {
+        let screen = screen.clone();
+        move || screen.remove()
+    });
+    document
+        .body()
+        .unwrap_throw()
+        .append_child(&screen)
+        .unwrap_throw();
+}
@@ -0,0 +1,226 @@
+//! Runs the games of the "online" mode. Every room's game is simulated here
+//! and streamed to the browsers playing it, which only send their turns.
+//!
+//! ```text
+//! cargo run --release --bin server -- --addr 127.0.0.1:9001
+//! ```
+
+mod room;
+
+use room::Room;
+use snake_game::net::{ClientMessage, ServerMessage, DEFAULT_PORT};
+use snake_game::random::{random_seed, Rng};
+use snake_game::TICK_INTERVAL;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::process::exit;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tungstenite::{Error, Message, WebSocket};
+
+/// How long a connection waits for a message before checking its outbox.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Letters room codes are made of, leaving out the easily confused I and O.
+const CODE_LETTERS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
+const CODE_LENGTH: usize = 4;
+
+const USAGE: &str = "usage: server [--addr HOST:PORT]";
+
+struct Server {
+    rooms: HashMap<String, Room>,
+    rng: Rng,
+    next_id: usize,
+}
+
+impl Server {
+    fn new_code(&mut self) -> String {
+        loop {
+            let code = (0..CODE_LENGTH)
+                .map(|_| CODE_LETTERS[self.rng.range(0, CODE_LETTERS.len())] as char)
+                .collect::<String>();
+            if !self.rooms.contains_key(&code) {
+                return code;
+            }
+        }
+    }
+
+    fn leave(&mut self, id: usize, code: &str) {
+        if let Some(room) = self.rooms.get_mut(code) {
+            room.leave(id);
+            if room.is_empty() {
+                self.rooms.remove(code);
+            }
+        }
+    }
+}
+
+struct Connection {
+    id: usize,
+    outbox: Sender<ServerMessage>,
+    /// Code of the room the connection is in.
+    room: Option<String>,
+}
+
+fn handle(
+    server: &mut Server,
+    connection: &mut Connection,
+    message: ClientMessage,
+) -> Result<(), String> {
+    match message {
+        ClientMessage::Create { .. } | ClientMessage::Join { .. } => {
+            if let Some(code) = connection.room.take() {
+                server.leave(connection.id, &code);
+            }
+            let code = match message {
+                ClientMessage::Join { code } => code,
+                ClientMessage::Create { lockstep } => {
+                    let code = server.new_code();
+                    server
+                        .rooms
+                        .insert(code.clone(), Room::new(code.clone(), lockstep));
+                    code
+                }
+                _ => unreachable!(),
+            };
+            server
+                .rooms
+                .get_mut(&code)
+                .ok_or_else(|| format!("There is no room {}", code))?
+                .join(connection.id, connection.outbox.clone())?;
+            connection.room = Some(code);
+        }
+        message => {
+            let room = connection
+                .room
+                .as_ref()
+                .and_then(|code| server.rooms.get_mut(code))
+                .ok_or("You're not in a room")?;
+            match message {
+                ClientMessage::Start => room.start(connection.id)?,
+                ClientMessage::Input { seq, direction } => {
+                    room.input(connection.id, seq, direction)
+                }
+                ClientMessage::Resync => room.resync(connection.id),
+                ClientMessage::Relay { message } => room.relay(connection.id, message),
+                ClientMessage::Finished => room.finish(),
+                ClientMessage::Create { .. } | ClientMessage::Join { .. } => unreachable!(),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Relays messages between a client and its room until the connection
+/// fails or is closed, and returns why.
+fn relay(
+    socket: &mut WebSocket<TcpStream>,
+    inbox: &Receiver<ServerMessage>,
+    server: &Mutex<Server>,
+    connection: &mut Connection,
+) -> Error {
+    loop {
+        while let Ok(message) = inbox.try_recv() {
+            if let Err(e) = socket.send(Message::Text(serde_json::to_string(&message).unwrap())) {
+                return e;
+            }
+        }
+        let text = match socket.read() {
+            Ok(Message::Text(text)) => text,
+            Ok(_) => continue,
+            Err(Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                continue
+            }
+            Err(e) => return e,
+        };
+        let result = match serde_json::from_str(&text) {
+            Ok(message) => handle(&mut server.lock().unwrap(), connection, message),
+            Err(e) => Err(format!("Invalid message: {}", e)),
+        };
+        if let Err(message) = result {
+            let _ = connection.outbox.send(ServerMessage::Error { message });
+        }
+    }
+}
+
+fn serve(stream: TcpStream, server: Arc<Mutex<Server>>) {
+    let peer = stream
+        .peer_addr()
+        .map_or("unknown peer".to_string(), |addr| addr.to_string());
+    let mut socket = match tungstenite::accept(stream) {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("{}: handshake failed: {}", peer, e);
+            return;
+        }
+    };
+    if let Err(e) = socket.get_ref().set_read_timeout(Some(POLL_INTERVAL)) {
+        eprintln!("{}: {}", peer, e);
+        return;
+    }
+    let (outbox, inbox) = channel();
+    let mut connection = Connection {
+        id: {
+            let mut server = server.lock().unwrap();
+            server.next_id += 1;
+            server.next_id
+        },
+        outbox,
+        room: None,
+    };
+    println!("{} connected", peer);
+    match relay(&mut socket, &inbox, &server, &mut connection) {
+        Error::ConnectionClosed => println!("{} disconnected", peer),
+        e => eprintln!("{}: {}", peer, e),
+    }
+    if let Some(code) = connection.room {
+        server.lock().unwrap().leave(connection.id, &code);
+    }
+}
+
+fn main() {
+    let mut addr = format!("127.0.0.1:{}", DEFAULT_PORT);
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        match (flag.as_str(), args.next()) {
+            ("--addr", Some(value)) => addr = value,
+            _ => {
+                eprintln!("{}", USAGE);
+                exit(2);
+            }
+        }
+    }
+    let listener = TcpListener::bind(&addr).unwrap_or_else(|e| {
+        eprintln!("could not listen on {}: {}", addr, e);
+        exit(1);
+    });
+    println!("listening on ws://{}", addr);
+
+    let server = Arc::new(Mutex::new(Server {
+        rooms: HashMap::new(),
+        rng: Rng::new(random_seed()),
+        next_id: 0,
+    }));
+    thread::spawn({
+        let server = server.clone();
+        move || loop {
+            thread::sleep(Duration::from_millis(TICK_INTERVAL as u64));
+            for room in server.lock().unwrap().rooms.values_mut() {
+                room.tick();
+            }
+        }
+    });
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let server = server.clone();
+                thread::spawn(move || serve(stream, server));
+            }
+            Err(e) => eprintln!("connection failed: {}", e),
+        }
+    }
+}
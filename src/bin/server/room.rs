@@ -0,0 +1,257 @@
+use snake_game::net::{PeerMessage, ServerMessage, Snapshot, KEYFRAME_INTERVAL, MAX_PLAYERS};
+use snake_game::random::random_seed;
+use snake_game::snake::{Direction, SnakeGame};
+use snake_game::{FOOD_COUNT, GAME_HEIGHT, GAME_WIDTH};
+use std::sync::mpsc::Sender;
+
+struct Member {
+    id: usize,
+    outbox: Sender<ServerMessage>,
+}
+
+fn send(members: &[Member], id: usize, message: ServerMessage) {
+    if let Some(member) = members.iter().find(|member| member.id == id) {
+        // The member's connection has closed if this fails, and it will
+        // leave the room on its own.
+        let _ = member.outbox.send(message);
+    }
+}
+
+/// A game played by the server and which member steers each of its snakes.
+struct Match {
+    game: SnakeGame,
+    /// Member id of every player, in player order.
+    players: Vec<usize>,
+    /// Last input of every player that has been queued.
+    acked: Vec<u32>,
+    last: Snapshot,
+}
+
+/// Players who share a room code, and the game they are playing if any.
+pub struct Room {
+    code: String,
+    /// Whether the players run the game themselves and the room only relays
+    /// their messages.
+    lockstep: bool,
+    /// In the order they joined; the first one is the host.
+    members: Vec<Member>,
+    current: Option<Match>,
+    /// Member id of every player of the running lockstep game.
+    relaying: Option<Vec<usize>>,
+}
+
+impl Room {
+    pub fn new(code: String, lockstep: bool) -> Self {
+        Self {
+            code,
+            lockstep,
+            members: vec![],
+            current: None,
+            relaying: None,
+        }
+    }
+
+    fn is_playing(&self) -> bool {
+        self.current.is_some() || self.relaying.is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    fn broadcast_lobby(&self) {
+        for (you, member) in self.members.iter().enumerate() {
+            let _ = member.outbox.send(ServerMessage::Lobby {
+                code: self.code.clone(),
+                lockstep: self.lockstep,
+                players: self.members.len(),
+                you,
+            });
+        }
+    }
+
+    pub fn join(&mut self, id: usize, outbox: Sender<ServerMessage>) -> Result<(), String> {
+        if self.is_playing() {
+            return Err(format!("Room {} is already playing", self.code));
+        }
+        if self.members.len() >= MAX_PLAYERS {
+            return Err(format!("Room {} is full", self.code));
+        }
+        self.members.push(Member { id, outbox });
+        self.broadcast_lobby();
+        Ok(())
+    }
+
+    pub fn leave(&mut self, id: usize) {
+        self.members.retain(|member| member.id != id);
+        if let Some(current) = &mut self.current {
+            if let Some(player) = current.players.iter().position(|other| *other == id) {
+                current.game.forfeit(player);
+            }
+        } else if let Some(players) = &self.relaying {
+            if let Some(player) = players.iter().position(|other| *other == id) {
+                for other in players {
+                    send(&self.members, *other, ServerMessage::Left { player });
+                }
+            }
+        } else {
+            self.broadcast_lobby();
+        }
+    }
+
+    pub fn start(&mut self, id: usize) -> Result<(), String> {
+        if self.members.first().map(|member| member.id) != Some(id) {
+            return Err("Only the host can start the game".to_string());
+        }
+        if self.is_playing() {
+            return Ok(());
+        }
+        if self.lockstep {
+            let players = self
+                .members
+                .iter()
+                .map(|member| member.id)
+                .collect::<Vec<_>>();
+            let seed = random_seed();
+            for (you, id) in players.iter().enumerate() {
+                send(
+                    &self.members,
+                    *id,
+                    ServerMessage::Started {
+                        you,
+                        players: players.len(),
+                        seed,
+                    },
+                );
+            }
+            self.relaying = Some(players);
+            return Ok(());
+        }
+        let game = SnakeGame::with_players(
+            GAME_WIDTH,
+            GAME_HEIGHT,
+            FOOD_COUNT,
+            self.members.len(),
+            random_seed(),
+        );
+        let current = Match {
+            last: game.snapshot(),
+            game,
+            players: self.members.iter().map(|member| member.id).collect(),
+            acked: vec![0; self.members.len()],
+        };
+        for (you, id) in current.players.iter().enumerate() {
+            send(
+                &self.members,
+                *id,
+                ServerMessage::Snapshot {
+                    you,
+                    acked: 0,
+                    snapshot: current.last.clone(),
+                },
+            );
+        }
+        self.current = Some(current);
+        Ok(())
+    }
+
+    pub fn input(&mut self, id: usize, seq: u32, direction: Direction) {
+        let Some(current) = &mut self.current else {
+            return;
+        };
+        let Some(player) = current.players.iter().position(|other| *other == id) else {
+            return;
+        };
+        current.game.change_direction(player, direction);
+        current.acked[player] = current.acked[player].max(seq);
+    }
+
+    /// Passes `message` on to every other player of the lockstep game.
+    pub fn relay(&self, id: usize, message: PeerMessage) {
+        let Some(players) = &self.relaying else {
+            return;
+        };
+        let Some(from) = players.iter().position(|other| *other == id) else {
+            return;
+        };
+        for other in players.iter().filter(|other| **other != id) {
+            send(
+                &self.members,
+                *other,
+                ServerMessage::Relay { from, message },
+            );
+        }
+    }
+
+    /// Ends the lockstep game once a player's copy of it is over. Every
+    /// copy ends on the same tick, so the first to say so is enough.
+    pub fn finish(&mut self) {
+        if self.relaying.take().is_some() {
+            self.broadcast_lobby();
+        }
+    }
+
+    pub fn resync(&self, id: usize) {
+        let Some(current) = &self.current else {
+            return;
+        };
+        if let Some(you) = current.players.iter().position(|other| *other == id) {
+            send(
+                &self.members,
+                id,
+                ServerMessage::Snapshot {
+                    you,
+                    acked: current.acked[you],
+                    snapshot: current.last.clone(),
+                },
+            );
+        }
+    }
+
+    /// Advances the room's game, if any, and tells every player what changed.
+    pub fn tick(&mut self) {
+        let Some(current) = &mut self.current else {
+            return;
+        };
+        current.game.tick();
+        let snapshot = current.game.snapshot();
+        if current.game.finished {
+            let outcome = current.game.outcome();
+            for id in &current.players {
+                send(
+                    &self.members,
+                    *id,
+                    ServerMessage::GameOver {
+                        outcome,
+                        snapshot: snapshot.clone(),
+                    },
+                );
+            }
+            self.current = None;
+            self.broadcast_lobby();
+            return;
+        }
+        let delta = current.last.delta_to(&snapshot);
+        current.last = snapshot;
+        let keyframe = current.last.tick % KEYFRAME_INTERVAL == 0;
+        for (player, id) in current.players.iter().enumerate() {
+            let acked = current.acked[player];
+            send(
+                &self.members,
+                *id,
+                if keyframe {
+                    ServerMessage::Snapshot {
+                        you: player,
+                        acked,
+                        snapshot: current.last.clone(),
+                    }
+                } else {
+                    ServerMessage::Delta {
+                        acked,
+                        delta: delta.clone(),
+                    }
+                },
+            );
+        }
+    }
+}
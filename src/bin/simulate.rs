@@ -0,0 +1,169 @@
+//! Plays many seeded games with a computer player as fast as possible and
+//! reports how they went.
+//!
+//! ```text
+//! cargo run --release --bin simulate -- --strategy astar --games 1000 --width 20 --height 20 --food 50
+//! ```
+
+use snake_game::ai::StrategyKind;
+use snake_game::snake::{GameEvent, SnakeGame};
+use std::collections::BTreeMap;
+use std::process::exit;
+use std::time::Instant;
+
+struct Options {
+    strategy: StrategyKind,
+    games: usize,
+    width: usize,
+    height: usize,
+    food: usize,
+    seed: u64,
+    max_ticks: usize,
+}
+
+const USAGE: &str = "usage: simulate [--strategy random|greedy|astar|hamiltonian] [--games N] \
+[--width N] [--height N] [--food N] [--seed N] [--max-ticks N]";
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        strategy: StrategyKind::Greedy,
+        games: 1000,
+        width: 20,
+        height: 20,
+        food: 50,
+        seed: 0,
+        max_ticks: 100_000,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", flag))?;
+        let number = || {
+            value
+                .parse::<usize>()
+                .map_err(|e| format!("invalid value for {}: {}", flag, e))
+        };
+        match flag.as_str() {
+            "--strategy" => {
+                options.strategy = StrategyKind::from_name(&value)
+                    .ok_or_else(|| format!("unknown strategy {}", value))?
+            }
+            "--games" => options.games = number()?,
+            "--width" => options.width = number()?,
+            "--height" => options.height = number()?,
+            "--food" => options.food = number()?,
+            "--seed" => options.seed = number()? as u64,
+            "--max-ticks" => options.max_ticks = number()?,
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+    Ok(options)
+}
+
+struct Outcome {
+    score: usize,
+    ticks: usize,
+    /// How the game ended, `None` if it was cut off by `--max-ticks`.
+    cause: Option<String>,
+}
+
+fn play(options: &Options, seed: u64) -> Outcome {
+    let mut game = SnakeGame::with_seed(options.width, options.height, options.food, seed);
+    // Seeded like the game, so every run of the same options plays the
+    // same games.
+    let mut strategy = options.strategy.build_with_seed(seed);
+    let mut cause = None;
+    while !game.finished && game.ticks < options.max_ticks {
+        game.change_direction(0, strategy.next_direction(&game.view(0)));
+        for event in game.tick() {
+            match event {
+                GameEvent::Died { cause: died_of, .. } => {
+                    cause = Some(format!("death by {:?}", died_of));
+                }
+                GameEvent::FilledBoard { .. } => cause = Some("filled board".to_string()),
+                _ => {}
+            }
+        }
+    }
+    Outcome {
+        score: game.score(0),
+        ticks: game.snakes[0].ticks,
+        cause,
+    }
+}
+
+fn percentile(sorted: &[usize], p: usize) -> usize {
+    sorted[(sorted.len() - 1) * p / 100]
+}
+
+fn main() {
+    let options = parse_options().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        exit(2);
+    });
+    if options.games == 0 || options.width == 0 || options.height == 0 {
+        eprintln!("games, width and height must be positive\n{}", USAGE);
+        exit(2);
+    }
+
+    let start = Instant::now();
+    let outcomes = (0..options.games as u64)
+        .map(|i| play(&options, options.seed + i))
+        .collect::<Vec<_>>();
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let mut scores = outcomes.iter().map(|o| o.score).collect::<Vec<_>>();
+    scores.sort_unstable();
+    let total_ticks = outcomes.iter().map(|o| o.ticks).sum::<usize>();
+    let mut causes = BTreeMap::new();
+    for outcome in &outcomes {
+        let cause = outcome.cause.as_deref().unwrap_or("timeout");
+        *causes.entry(cause).or_insert(0) += 1;
+    }
+
+    println!(
+        "{} games of {}x{} with {} food, strategy {}",
+        options.games,
+        options.width,
+        options.height,
+        options.food,
+        options.strategy.name()
+    );
+    println!(
+        "score: min {} / p10 {} / median {} / mean {:.1} / p90 {} / max {}",
+        scores[0],
+        percentile(&scores, 10),
+        percentile(&scores, 50),
+        scores.iter().sum::<usize>() as f64 / scores.len() as f64,
+        percentile(&scores, 90),
+        scores[scores.len() - 1]
+    );
+    let bucket = (scores[scores.len() - 1] / 10).max(1);
+    let mut histogram = BTreeMap::new();
+    for score in &scores {
+        *histogram.entry(score / bucket).or_insert(0) += 1;
+    }
+    for (i, count) in histogram {
+        println!(
+            "  {:>5}-{:<5} {:>6} {}",
+            i * bucket,
+            (i + 1) * bucket - 1,
+            count,
+            "#".repeat(count * 50 / options.games)
+        );
+    }
+    println!(
+        "survival: {:.1} ticks on average",
+        total_ticks as f64 / options.games as f64
+    );
+    for (cause, count) in causes {
+        println!("{}: {}", cause, count);
+    }
+    println!(
+        "throughput: {:.0} ticks/s ({} ticks in {:.2}s)",
+        total_ticks as f64 / elapsed,
+        total_ticks,
+        elapsed
+    );
+}
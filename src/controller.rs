@@ -6,6 +6,10 @@ use web_sys::{window, HtmlButtonElement, HtmlElement, KeyboardEvent};
 
 use crate::snake::Direction;
 
+/// The key player one holds down to boost. Only solo games have boost, so
+/// player two has none.
+const BOOST_KEY: &str = " ";
+
 fn is_mobile() -> bool {
     let window = window().unwrap_throw();
     let user_agent = window.navigator().user_agent().unwrap_throw();
@@ -22,12 +26,22 @@ fn is_mobile() -> bool {
     mobile_agents.is_match(&user_agent)
 }
 
+/// Something a player did with the keyboard or the on-screen pad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Turn(Direction),
+    /// Boost was pressed down, or let go of.
+    Boost(bool),
+}
+
+/// Reports `(player, input)` pairs: WASD, space and the on-screen pad are
+/// player one's, the arrow keys player two's.
 pub struct Controller {
-    oninput: CallbackFn<Direction>,
+    oninput: CallbackFn<(usize, Input)>,
 }
 
 impl Controller {
-    pub fn new(oninput: Box<dyn FnMut(Direction)>) -> Self {
+    pub fn new(oninput: Box<dyn FnMut((usize, Input))>) -> Self {
         let mut controller = Self {
             oninput: Rc::new(RefCell::new(oninput)),
         };
@@ -84,22 +98,33 @@ impl Controller {
                     .unwrap_throw()
                     .dyn_into::<HtmlButtonElement>()
                     .unwrap_throw();
+                let boost = document
+                    .create_element("button")
+                    .unwrap_throw()
+                    .dyn_into::<HtmlButtonElement>()
+                    .unwrap_throw();
                 left.set_inner_html("&larr;");
                 right.set_inner_html("&rarr;");
+                boost.set_inner_html("boost");
                 style!(middle {
                     "display": "flex";
                     "width": "300px";
                     "height": "34%";
                 });
                 style!(left {
-                    "width": "50%";
+                    "width": "35%";
+                    "height": "100%";
+                });
+                style!(boost {
+                    "width": "30%";
                     "height": "100%";
                 });
                 style!(right {
-                    "width": "50%";
+                    "width": "35%";
                     "height": "100%";
                 });
                 middle.append_child(&left).unwrap_throw();
+                middle.append_child(&boost).unwrap_throw();
                 middle.append_child(&right).unwrap_throw();
                 root.append_child(&middle).unwrap_throw();
                 let bottom = document
@@ -116,46 +141,102 @@ impl Controller {
                 onclick!(top -> {
                     let oninput = self.oninput.clone();
                     move || {
-                        oninput.borrow_mut()(Direction::Up);
+                        oninput.borrow_mut()((0, Input::Turn(Direction::Up)));
                     }
                 });
                 onclick!(left -> {
                     let oninput = self.oninput.clone();
                     move || {
-                        oninput.borrow_mut()(Direction::Left);
+                        oninput.borrow_mut()((0, Input::Turn(Direction::Left)));
                     }
                 });
                 onclick!(right -> {
                     let oninput = self.oninput.clone();
                     move || {
-                        oninput.borrow_mut()(Direction::Right);
+                        oninput.borrow_mut()((0, Input::Turn(Direction::Right)));
                     }
                 });
                 onclick!(bottom -> {
                     let oninput = self.oninput.clone();
                     move || {
-                        oninput.borrow_mut()(Direction::Down);
+                        oninput.borrow_mut()((0, Input::Turn(Direction::Down)));
                     }
                 });
+                // Boost lasts as long as the button is held, so it listens
+                // for the pointer going down and coming off it instead of
+                // clicks.
+                for (held, events) in [
+                    (true, vec!["pointerdown"]),
+                    (false, vec!["pointerup", "pointerleave", "pointercancel"]),
+                ] {
+                    let onboost = Closure::wrap(Box::new({
+                        let oninput = self.oninput.clone();
+                        move || {
+                            oninput.borrow_mut()((0, Input::Boost(held)));
+                        }
+                    }) as Box<dyn FnMut()>);
+                    for event in events {
+                        boost
+                            .add_event_listener_with_callback(
+                                event,
+                                onboost.as_ref().unchecked_ref(),
+                            )
+                            .unwrap_throw();
+                    }
+                    onboost.forget();
+                }
             };
             return;
         };
         let keydown_closure = Closure::wrap(Box::new({
             let oninput = self.oninput.clone();
             move |e: KeyboardEvent| {
-                let direction = match e.key().as_ref() {
-                    "ArrowUp" => Some(Direction::Up),
-                    "ArrowDown" => Some(Direction::Down),
-                    "ArrowLeft" => Some(Direction::Left),
-                    "ArrowRight" => Some(Direction::Right),
+                let turn = |player, direction| Some((player, Input::Turn(direction)));
+                let input = match e.key().to_lowercase().as_ref() {
+                    "w" => turn(0, Direction::Up),
+                    "s" => turn(0, Direction::Down),
+                    "a" => turn(0, Direction::Left),
+                    "d" => turn(0, Direction::Right),
+                    "arrowup" => turn(1, Direction::Up),
+                    "arrowdown" => turn(1, Direction::Down),
+                    "arrowleft" => turn(1, Direction::Left),
+                    "arrowright" => turn(1, Direction::Right),
+                    BOOST_KEY => {
+                        // Otherwise the key also clicks whichever menu
+                        // button was last clicked, which keeps the focus.
+                        e.prevent_default();
+                        // Held keys repeat, but boost is only pressed once.
+                        (!e.repeat()).then_some((0, Input::Boost(true)))
+                    }
                     _ => None,
                 };
-                if let Some(direction) = direction {
-                    oninput.borrow_mut()(direction);
+                if let Some(input) = input {
+                    oninput.borrow_mut()(input);
                 };
             }
         }) as Box<dyn FnMut(KeyboardEvent)>);
         window.set_onkeydown(keydown_closure.as_ref().dyn_ref());
         keydown_closure.forget();
+        let keyup_closure = Closure::wrap(Box::new({
+            let oninput = self.oninput.clone();
+            move |e: KeyboardEvent| {
+                if e.key() == BOOST_KEY {
+                    // Buttons are clicked by space coming back up.
+                    e.prevent_default();
+                    oninput.borrow_mut()((0, Input::Boost(false)));
+                }
+            }
+        }) as Box<dyn FnMut(KeyboardEvent)>);
+        window.set_onkeyup(keyup_closure.as_ref().dyn_ref());
+        keyup_closure.forget();
+        // A key let go of in another window never comes up in this one.
+        let blur_closure = Closure::wrap(Box::new({
+            let oninput = self.oninput.clone();
+            move || {
+                oninput.borrow_mut()((0, Input::Boost(false)));
+            }
+        }) as Box<dyn FnMut()>);
+        window.set_onblur(blur_closure.as_ref().dyn_ref());
+        blur_closure.forget();
     }
 }
@@ -0,0 +1,241 @@
+use crate::random::Rng;
+use crate::snake::{Rules, SelfCollision};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use web_sys::window;
+
+const MS_PER_DAY: f64 = 86_400_000.0;
+/// Mixed into the day number, so daily seeds don't follow each other.
+const SEED_SALT: u64 = 0x5a4b_e0a1_d417_c0de;
+/// Food counts a daily challenge picks from.
+const FOOD_COUNTS: [usize; 3] = [10, 25, 50];
+/// Rule sets a daily challenge picks from. Time limits are in ticks.
+const RULE_SETS: [Rules; 4] = [
+    Rules {
+        self_collision: SelfCollision::Die,
+        wrap: false,
+        time_limit: None,
+        portals: 0,
+        hazards: 0,
+        moving_food: false,
+        rot_ticks: None,
+        ends_with_player_one: false,
+    },
+    Rules {
+        self_collision: SelfCollision::Die,
+        wrap: true,
+        time_limit: None,
+        portals: 0,
+        hazards: 0,
+        moving_food: false,
+        rot_ticks: None,
+        ends_with_player_one: false,
+    },
+    Rules {
+        self_collision: SelfCollision::Die,
+        wrap: false,
+        time_limit: Some(240),
+        portals: 0,
+        hazards: 0,
+        moving_food: false,
+        rot_ticks: None,
+        ends_with_player_one: false,
+    },
+    Rules {
+        self_collision: SelfCollision::Die,
+        wrap: true,
+        time_limit: Some(120),
+        portals: 0,
+        hazards: 0,
+        moving_food: false,
+        rot_ticks: None,
+        ends_with_player_one: false,
+    },
+];
+
+/// The game everybody plays on a given day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Challenge {
+    /// Days since 1970-01-01, in UTC so that the day changes at the same
+    /// moment for everyone.
+    pub day: i64,
+    pub seed: u64,
+    pub food_count: usize,
+    pub rules: Rules,
+}
+
+impl Challenge {
+    pub fn for_day(day: i64) -> Self {
+        let mut rng = Rng::new(SEED_SALT ^ day as u64);
+        Self {
+            day,
+            seed: rng.next_u64(),
+            food_count: FOOD_COUNTS[rng.range(0, FOOD_COUNTS.len())],
+            rules: RULE_SETS[rng.range(0, RULE_SETS.len())],
+        }
+    }
+
+    pub fn today() -> Self {
+        Self::for_day((js_sys::Date::now() / MS_PER_DAY).floor() as i64)
+    }
+
+    /// The day as `YYYY-MM-DD`.
+    pub fn date(&self) -> String {
+        // Howard Hinnant's `civil_from_days`.
+        let z = self.day + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as i64;
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+/// The player's daily challenge results.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DailyRecord {
+    /// The last day a challenge was played.
+    pub day: Option<i64>,
+    /// Best score on that day.
+    pub best: usize,
+    /// Days in a row a challenge was played, up to that day.
+    pub streak: usize,
+    pub longest_streak: usize,
+}
+
+impl DailyRecord {
+    /// Adds a finished game of the challenge of `day`.
+    pub fn record(&mut self, day: i64, score: usize) {
+        match self.day {
+            Some(last) if last == day => self.best = self.best.max(score),
+            Some(last) if last > day => {}
+            last => {
+                self.streak = if last == Some(day - 1) {
+                    self.streak + 1
+                } else {
+                    1
+                };
+                self.longest_streak = self.longest_streak.max(self.streak);
+                self.day = Some(day);
+                self.best = score;
+            }
+        }
+    }
+
+    /// Best score on `day`, if it was played.
+    pub fn best_on(&self, day: i64) -> Option<usize> {
+        (self.day == Some(day)).then_some(self.best)
+    }
+
+    /// Keeps the more recent of two records, or the better one of the same
+    /// day.
+    pub fn merge(&mut self, other: DailyRecord) {
+        self.longest_streak = self.longest_streak.max(other.longest_streak);
+        if other.day > self.day {
+            let longest_streak = self.longest_streak;
+            *self = other;
+            self.longest_streak = longest_streak;
+        } else if other.day == self.day {
+            self.best = self.best.max(other.best);
+            self.streak = self.streak.max(other.streak);
+        }
+    }
+}
+
+/// A summary of a challenge game to share, with no spoilers about the board.
+pub fn share_text(challenge: &Challenge, score: usize, length: usize, streak: usize) -> String {
+    format!(
+        "🐍 Snake daily {}\nscore: {}\nlength: {}\nstreak: {} {}",
+        challenge.date(),
+        score,
+        length,
+        streak,
+        if streak == 1 { "day" } else { "days" }
+    )
+}
+
+pub fn copy_to_clipboard(text: &str) {
+    let _ = window()
+        .unwrap_throw()
+        .navigator()
+        .clipboard()
+        .write_text(text);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn date_follows_the_calendar() {
+        let dates = [
+            (-1, "1969-12-31"),
+            (0, "1970-01-01"),
+            (59, "1970-03-01"),
+            (11_016, "2000-02-29"),
+            (20_088, "2024-12-31"),
+            (47_541, "2100-03-01"),
+        ];
+        for (day, date) in dates {
+            assert_eq!(Challenge::for_day(day).date(), date);
+        }
+    }
+
+    #[test]
+    fn every_day_has_the_same_challenge_for_everyone() {
+        assert_eq!(Challenge::for_day(20_000), Challenge::for_day(20_000));
+        assert_ne!(
+            Challenge::for_day(20_000).seed,
+            Challenge::for_day(20_001).seed
+        );
+    }
+
+    #[test]
+    fn streaks_count_days_in_a_row() {
+        let mut record = DailyRecord::default();
+        for day in 100..103 {
+            record.record(day, 5);
+        }
+        assert_eq!((record.streak, record.longest_streak), (3, 3));
+        // Playing again the same day keeps the best score but no more streak.
+        record.record(102, 9);
+        record.record(102, 4);
+        assert_eq!((record.streak, record.best), (3, 9));
+        // A missed day starts over, but the longest streak stays.
+        record.record(104, 2);
+        assert_eq!((record.streak, record.longest_streak), (1, 3));
+        assert_eq!(record.best_on(104), Some(2));
+        assert_eq!(record.best_on(102), None);
+    }
+
+    #[test]
+    fn games_of_earlier_days_are_ignored() {
+        let mut record = DailyRecord::default();
+        record.record(50, 3);
+        record.record(49, 10);
+        assert_eq!(record.day, Some(50));
+        assert_eq!((record.streak, record.best), (1, 3));
+    }
+
+    #[test]
+    fn merge_keeps_the_later_record_and_the_longest_streak() {
+        let mut older = DailyRecord::default();
+        for day in 10..15 {
+            older.record(day, 1);
+        }
+        let mut newer = DailyRecord::default();
+        newer.record(20, 7);
+        older.merge(newer.clone());
+        assert_eq!(older.day, Some(20));
+        assert_eq!((older.streak, older.longest_streak, older.best), (1, 5, 7));
+        let mut same_day = DailyRecord::default();
+        same_day.record(20, 12);
+        newer.merge(same_day);
+        assert_eq!(newer.best, 12);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::helper::Vector;
+use crate::snake::Direction;
+use serde::{Deserialize, Serialize};
+
+/// Something on the board besides the snakes and the food.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum EntityKind {
+    /// Moves in a straight line and bounces back off whatever it runs into.
+    Block,
+    /// Walks straight ahead and turns clockwise when it can't.
+    Patroller,
+    /// Steps toward the closest snake head.
+    Chaser,
+    /// Stays put. Eating it powers a snake up, so it can eat the others.
+    Star,
+}
+
+impl EntityKind {
+    /// The kinds that kill a snake they touch.
+    pub const HAZARDS: [EntityKind; 3] =
+        [EntityKind::Block, EntityKind::Patroller, EntityKind::Chaser];
+
+    pub fn is_hazard(self) -> bool {
+        self != EntityKind::Star
+    }
+
+    /// Ticks between two moves, or `None` if the entity doesn't move.
+    pub fn period(self) -> Option<usize> {
+        match self {
+            EntityKind::Block | EntityKind::Patroller => Some(2),
+            EntityKind::Chaser => Some(3),
+            EntityKind::Star => None,
+        }
+    }
+
+    pub fn icon(self) -> char {
+        match self {
+            EntityKind::Block => '🪨',
+            EntityKind::Patroller => '👾',
+            EntityKind::Chaser => '👻',
+            EntityKind::Star => '⭐',
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct Entity {
+    pub kind: EntityKind,
+    pub pos: Vector,
+    /// The way it last moved, or is about to.
+    pub direction: Direction,
+}
+
+fn clockwise(direction: Direction) -> Direction {
+    match direction {
+        Direction::Up => Direction::Right,
+        Direction::Right => Direction::Down,
+        Direction::Down => Direction::Left,
+        Direction::Left => Direction::Up,
+    }
+}
+
+/// Steps between two cells on an empty board.
+pub(crate) fn distance(a: &Vector, b: &Vector) -> isize {
+    (a.0 - b.0).abs() + (a.1 - b.1).abs()
+}
+
+impl Entity {
+    /// Where the entity moves next and the direction it takes to get there,
+    /// if it moves at all. It only moves into cells `is_open` allows.
+    /// `heads` are the heads of the snakes still alive.
+    pub fn next_move(
+        &self,
+        is_open: impl Fn(&Vector) -> bool,
+        heads: &[Vector],
+    ) -> Option<(Vector, Direction)> {
+        let step = |direction: Direction| {
+            let pos = &self.pos + direction.to_vec();
+            is_open(&pos).then_some((pos, direction))
+        };
+        match self.kind {
+            EntityKind::Block => step(self.direction).or_else(|| step(self.direction.opposite())),
+            EntityKind::Patroller => {
+                let right = clockwise(self.direction);
+                [
+                    self.direction,
+                    right,
+                    right.opposite(),
+                    self.direction.opposite(),
+                ]
+                .into_iter()
+                .find_map(step)
+            }
+            EntityKind::Chaser => {
+                let closest = |pos: &Vector| heads.iter().map(|head| distance(pos, head)).min();
+                let current = closest(&self.pos)?;
+                Direction::ALL
+                    .into_iter()
+                    .filter_map(step)
+                    .filter(|(pos, _)| closest(pos).is_some_and(|d| d < current))
+                    .min_by_key(|(pos, _)| closest(pos))
+            }
+            EntityKind::Star => None,
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crate::helper::Vector;
+use crate::renderer::{Cell, Frame, Overlay, Renderable};
+use crate::snake::{Direction, SnakeGame};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+const GHOST_OPACITY: f64 = 0.3;
+
+/// The head position and length of a snake after every tick of a game.
+///
+/// A snake's body is always its last `length` head positions, so this is
+/// enough to redraw the whole snake at any tick.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Ghost {
+    heads: Vec<Vector>,
+    lengths: Vec<usize>,
+}
+
+impl Ghost {
+    pub fn record(&mut self, snake: &VecDeque<Vector>) {
+        self.heads.push(snake[0]);
+        self.lengths.push(snake.len());
+    }
+
+    /// Whether the ghost could have come from a game: a head for every tick,
+    /// and never shorter than that head. Anything else can't be drawn.
+    pub fn is_valid(&self) -> bool {
+        self.heads.len() == self.lengths.len() && self.lengths.iter().all(|length| *length >= 1)
+    }
+
+    pub fn score(&self) -> usize {
+        self.lengths.last().map_or(0, |length| length - 1)
+    }
+
+    /// The ghost's body at `tick`, head first, or `None` once its run is over.
+    pub fn body_at(&self, tick: usize) -> Option<impl Iterator<Item = &Vector>> {
+        let length = *self.lengths.get(tick)?;
+        Some(self.heads[..=tick].iter().rev().take(length))
+    }
+}
+
+/// A live game with the personal best ghost drawn over it.
+pub struct GhostRace<'a> {
+    pub game: &'a SnakeGame,
+    pub ghost: Option<&'a Ghost>,
+}
+
+impl Renderable for GhostRace<'_> {
+    fn frame(&self) -> Frame {
+        let mut frame = self.game.frame();
+        if let Some(body) = self.ghost.and_then(|ghost| ghost.body_at(self.game.ticks)) {
+            let body = body.collect::<Vec<_>>();
+            let last = body.len() - 1;
+            for (i, pos) in body.iter().enumerate() {
+                let toward_head = i
+                    .checked_sub(1)
+                    .and_then(|prev| self.game.direction_between(pos, body[prev]));
+                let toward_tail = body
+                    .get(i + 1)
+                    .and_then(|next| self.game.direction_between(pos, next));
+                // Cells a portal keeps apart have no direction between them,
+                // so the snake is drawn going straight through.
+                let toward_head = toward_head.or(toward_tail.map(|d| d.opposite()));
+                let toward_tail = toward_tail.or(toward_head.map(|d| d.opposite()));
+                let cell = if i == 0 {
+                    Cell::Head {
+                        player: 0,
+                        facing: toward_tail.map_or(Direction::Left, |d| d.opposite()),
+                    }
+                } else if i == last {
+                    Cell::Tail {
+                        player: 0,
+                        toward_head: toward_head.unwrap_or(Direction::Left),
+                    }
+                } else {
+                    Cell::Body {
+                        player: 0,
+                        toward_head: toward_head.unwrap_or(Direction::Left),
+                        toward_tail: toward_tail.unwrap_or(Direction::Right),
+                    }
+                };
+                frame.overlays.push(Overlay {
+                    pos: **pos,
+                    cell,
+                    opacity: GHOST_OPACITY,
+                });
+            }
+        }
+        frame
+    }
+}
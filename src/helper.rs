@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::rc::Rc;
 use std::{cell::RefCell, ops::Add};
 use wasm_bindgen::prelude::*;
@@ -38,9 +39,20 @@ pub(crate) use onclick;
 pub(crate) use style;
 // pub(crate) use log;
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub struct Vector(pub isize, pub isize);
 
+impl Vector {
+    /// Where the cell at this position is kept in a row by row list of the
+    /// cells of a `width` by `height` board, or `None` if it is off the board.
+    pub fn index_in(&self, width: usize, height: usize) -> Option<usize> {
+        if self.0 < 0 || self.1 < 0 || self.0 >= width as isize || self.1 >= height as isize {
+            return None;
+        }
+        Some(self.1 as usize * width + self.0 as usize)
+    }
+}
+
 impl Add<Vector> for &Vector {
     type Output = Vector;
 
@@ -1,117 +1,842 @@
 use js_sys::Function;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use wasm_bindgen::prelude::*;
 use web_sys::window;
+pub mod ai;
+mod audio;
 mod controller;
-mod helper;
-mod random;
+mod daily;
+pub mod entity;
+mod ghost;
+pub mod helper;
+pub mod lockstep;
+mod menu;
+pub mod net;
+mod online;
+mod playback;
+pub mod random;
 mod renderer;
-mod snake;
+pub mod replay;
+mod save;
+pub mod snake;
+mod stats;
+use ai::*;
+use audio::*;
 use controller::*;
+use daily::*;
+use ghost::*;
+use menu::*;
+use online::*;
+use playback::*;
+use random::random_seed;
 use renderer::*;
+use save::*;
 use snake::*;
+use stats::*;
 
-const GAME_WIDTH: usize = 20;
-const GAME_HEIGHT: usize = 20;
+pub const GAME_WIDTH: usize = 20;
+pub const GAME_HEIGHT: usize = 20;
+pub const FOOD_COUNT: usize = 50;
+/// Milliseconds between two ticks of the game at normal speed.
+pub const TICK_INTERVAL: i32 = 500;
+/// How many times faster the game ticks while player one boosts.
+const BOOST_SPEEDUP: i32 = 3;
+/// Pairs of portals in portals mode.
+const PORTAL_PAIRS: usize = 3;
+/// Hazards on the board in hazards mode.
+const HAZARD_COUNT: usize = 4;
+/// Ticks food lasts before rotting in rotten food mode.
+const ROT_TICKS: usize = 80;
+/// Points between two level-up chimes.
+const LEVEL_LENGTH: usize = 10;
 
 thread_local! {
-    static GAME: RefCell<SnakeGame> = RefCell::new(SnakeGame::new(GAME_WIDTH, GAME_HEIGHT, 50));
-    static INTERVAL_ID: RefCell<Option<i32>> = RefCell::new(None);
+    static GAME: RefCell<SnakeGame> = RefCell::new(SnakeGame::new(GAME_WIDTH, GAME_HEIGHT, FOOD_COUNT));
+    static MODE: Cell<Mode> = const { Cell::new(Mode::Solo) };
+    static DIFFICULTY: Cell<Difficulty> = const { Cell::new(Difficulty::Normal) };
+    /// Computer players and the snake each of them steers. A computer
+    /// steering player one means the game is a demo.
+    static BOTS: RefCell<Vec<(usize, Box<dyn Strategy>)>> = const { RefCell::new(vec![]) };
+    /// The live game's next tick, while it is running.
+    static PENDING_TICK: Cell<Option<PendingTick>> = const { Cell::new(None) };
+    /// The daily challenge being played, in daily mode.
+    static CHALLENGE: Cell<Option<Challenge>> = const { Cell::new(None) };
+    /// Whether the current game has beaten the high score it started with.
+    static RECORD_BROKEN: Cell<bool> = const { Cell::new(false) };
+    /// Whether the live game was pasted in, which keeps it out of the
+    /// player's records.
+    static UNRANKED: Cell<bool> = const { Cell::new(false) };
+    static GAME_DATA: RefCell<GameData> = RefCell::new(load_game_data().unwrap_or_default());
     static TICK_CLOSURE: Closure<dyn FnMut()> = Closure::wrap(Box::new(|| {
-        GAME.with(|game| {
+        let running = GAME.with(|game| {
             RENDERER.with(|renderer| {
                 let renderer = &mut *renderer.borrow_mut();
-                game.borrow_mut().tick();
-                let game = game.borrow();
-                renderer.score = game.snake.len() - 1;
-                if game.finished {
-                    INTERVAL_ID.with(|interval_id| {
-                        let interval_id = *interval_id.borrow();
-                        if let Some(interval_id) = interval_id {
-                            window().unwrap_throw().clear_interval_with_handle(interval_id);
-                        }
-                    });
-                };
-                if renderer.score > renderer.high_score {
-                    let _ = save_game_data(GameData { high_score: renderer.score });
-                    renderer.high_score = renderer.score;
+                let game = &mut *game.borrow_mut();
+                tick_game(game, renderer);
+                if game.finished && is_demo() {
+                    *game = SnakeGame::new(GAME_WIDTH, GAME_HEIGHT, FOOD_COUNT)
+                        .with_rules(MODE.with(Cell::get).rules());
                 }
-                renderer.render(&*game);
+                !game.finished
             })
-        })
+        });
+        if running {
+            start_ticking();
+        } else {
+            stop_ticking();
+        }
     }));
     static RENDERER: RefCell<Renderer> = RefCell::new(Renderer {
         width: GAME_WIDTH,
         height: GAME_HEIGHT,
         pixel_size: 18,
         onclick: None,
-        score: 0,
-        high_score: load_game_data()
-            .unwrap_or_default()
-            .high_score
+        high_score: GAME_DATA.with(|data| data.borrow().high_score),
     });
     static CONTROLLER: Controller = Controller::new(Box::new({
-        move |direction| {
-            TICK_CLOSURE.with(|tick_closure| {
-                GAME.with(|game| {
-                        let mut game = game.borrow_mut();
-                        game.change_direction(direction);
-                        if game.finished { return };
-                        INTERVAL_ID.with(|interval_id| {
-                            let interval_id = &mut *interval_id.borrow_mut();
-
-                            RENDERER.with(|renderer| {
-                                let renderer = &mut *renderer.borrow_mut();
-                                game.tick();
-                                renderer.score = game.snake.len() - 1;
-                                if game.finished {
-                                    if let Some(id) = *interval_id {
-                                        window().unwrap_throw().clear_interval_with_handle(id);
-                                        *interval_id = None;
-                                    }
-                                };
-                                if renderer.score > renderer.high_score {
-                                    let _ = save_game_data(GameData { high_score: renderer.score });
-                                    renderer.high_score = renderer.score;
-                                };
-                                renderer.render(&*game);
-                            });
-
-                            if let Some(id) = *interval_id {
-                                let window = window().unwrap_throw();
-                                window.clear_interval_with_handle(id);
-                                *interval_id = Some(
-                                    window
-                                        .set_interval_with_callback_and_timeout_and_arguments_0(
-                                            tick_closure
-                                                .as_ref()
-                                                .dyn_ref::<Function>()
-                                                .unwrap_throw(),
-                                            500
-                                        ).unwrap_throw()
-                                    );
-                            };
-                    });
-                });
-            });
+        move |(player, input)| {
+            if is_online() {
+                if let Input::Turn(direction) = input {
+                    send_direction(direction);
+                }
+                return;
+            }
+            if is_playing_back() || is_demo() {
+                return;
+            }
+            match input {
+                Input::Turn(direction) => steer(player, direction),
+                Input::Boost(held) => boost(held),
+            }
         }
-    }) as Box<dyn FnMut(Direction)>);
+    }) as Box<dyn FnMut((usize, Input))>);
 }
 
-#[wasm_bindgen(start)]
-fn main() {
+/// Turns `player`'s snake in the live game. Player one's turns move the
+/// snake at once rather than waiting for the next tick.
+fn steer(player: usize, direction: Direction) {
+    let running = GAME.with(|game| {
+        let mut game = game.borrow_mut();
+        if MODE.with(Cell::get) == Mode::LocalVersus {
+            // Ticking on input would let one player speed up the
+            // other's snake, so queue the turn for the next tick.
+            game.change_direction(player, direction);
+            return None;
+        }
+        game.change_direction(0, direction);
+        if game.finished {
+            return None;
+        }
+        RENDERER.with(|renderer| {
+            let renderer = &mut *renderer.borrow_mut();
+            tick_game(&mut game, renderer);
+        });
+        Some(!game.finished)
+    });
+    match running {
+        Some(true) => start_ticking(),
+        Some(false) => stop_ticking(),
+        None => {}
+    }
+}
+
+/// Starts or stops player one boosting, which only solo games have: the
+/// whole game speeds up with the boost, so every snake would.
+fn boost(held: bool) {
+    if MODE.with(Cell::get).players() > 1 {
+        return;
+    }
+    GAME.with(|game| game.borrow_mut().set_boost(0, held));
+    // Paused games only start up again once the player steers.
+    if let Some(pending) = PENDING_TICK.with(Cell::get) {
+        if pending.delay != tick_delay() {
+            schedule_tick(pending.since);
+        }
+    }
+}
+
+/// A scheduled tick of the live game.
+#[derive(Debug, Clone, Copy)]
+struct PendingTick {
+    timeout: i32,
+    /// When the wait for the tick started, in milliseconds since the epoch.
+    since: f64,
+    /// Milliseconds the wait lasts.
+    delay: i32,
+}
+
+/// Milliseconds between two ticks of the live game, fewer while player one
+/// boosts.
+fn tick_delay() -> i32 {
+    if GAME.with(|game| game.borrow().snakes[0].is_boosting()) {
+        TICK_INTERVAL / BOOST_SPEEDUP
+    } else {
+        TICK_INTERVAL
+    }
+}
+
+/// (Re)starts the live game's ticking, with the next tick a full delay from
+/// now.
+fn start_ticking() {
+    schedule_tick(js_sys::Date::now());
+}
+
+/// Schedules the live game's next tick a delay after `since`, in place of
+/// any tick already scheduled. Time already waited since then counts, so
+/// the delay changing partway doesn't hold the tick back.
+fn schedule_tick(since: f64) {
+    let delay = tick_delay();
+    let wait = (since + delay as f64 - js_sys::Date::now()).max(0.0) as i32;
     let window = window().unwrap_throw();
-    TICK_CLOSURE.with(|tick_closure| {
-        INTERVAL_ID.with(|interval_id| {
-            *interval_id.borrow_mut() = Some(
-                window
-                    .set_interval_with_callback_and_timeout_and_arguments_0(
-                        tick_closure.as_ref().dyn_ref::<Function>().unwrap_throw(),
-                        500,
-                    )
-                    .unwrap_throw(),
-            );
+    if let Some(pending) = PENDING_TICK.with(Cell::get) {
+        window.clear_timeout_with_handle(pending.timeout);
+    }
+    let timeout = TICK_CLOSURE.with(|tick_closure| {
+        window
+            .set_timeout_with_callback_and_timeout_and_arguments_0(
+                tick_closure.as_ref().dyn_ref::<Function>().unwrap_throw(),
+                wait,
+            )
+            .unwrap_throw()
+    });
+    PENDING_TICK.with(|pending| {
+        pending.set(Some(PendingTick {
+            timeout,
+            since,
+            delay,
+        }))
+    });
+}
+
+fn stop_ticking() {
+    if let Some(pending) = PENDING_TICK.with(Cell::take) {
+        window()
+            .unwrap_throw()
+            .clear_timeout_with_handle(pending.timeout);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Solo,
+    /// A solo game with wrapping edges where biting yourself only costs
+    /// points. It never counts towards the records.
+    Zen,
+    /// A solo game with pairs of portals on the board.
+    Portals,
+    /// A solo game with moving hazards, and stars to eat them with.
+    Hazards,
+    /// A solo game where some of the food runs around.
+    Wildlife,
+    /// A solo game where food left uneaten rots into poison.
+    Rotten,
+    /// A solo game against a clock of this many seconds, with bonus time
+    /// for some food.
+    TimeAttack(usize),
+    /// The same board and rules for everyone, changing every day.
+    Daily,
+    /// Two players sharing the keyboard.
+    LocalVersus,
+    /// Player one against this many computer opponents.
+    VersusAi(usize),
+    /// A game run by a server, against other browsers.
+    Online,
+    /// A game every browser runs itself, kept in step by trading turns.
+    Lockstep,
+}
+
+impl Mode {
+    const ALL: [Mode; 14] = [
+        Mode::Solo,
+        Mode::Zen,
+        Mode::Portals,
+        Mode::Hazards,
+        Mode::Wildlife,
+        Mode::Rotten,
+        Mode::TimeAttack(60),
+        Mode::TimeAttack(120),
+        Mode::Daily,
+        Mode::LocalVersus,
+        Mode::VersusAi(1),
+        Mode::VersusAi(3),
+        Mode::Online,
+        Mode::Lockstep,
+    ];
+
+    fn name(self) -> String {
+        match self {
+            Mode::Solo => "solo".to_string(),
+            Mode::Zen => "zen".to_string(),
+            Mode::Portals => "portals".to_string(),
+            Mode::Hazards => "hazards".to_string(),
+            Mode::Wildlife => "wildlife".to_string(),
+            Mode::Rotten => "rotten food".to_string(),
+            Mode::TimeAttack(seconds) => format!("time attack {}s", seconds),
+            Mode::Daily => "daily".to_string(),
+            Mode::LocalVersus => "2 players".to_string(),
+            Mode::VersusAi(opponents) => format!("vs {} ai", opponents),
+            Mode::Online => "online".to_string(),
+            Mode::Lockstep => "online lockstep".to_string(),
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Mode> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+
+    fn players(self) -> usize {
+        match self {
+            Mode::Solo
+            | Mode::Zen
+            | Mode::Portals
+            | Mode::Hazards
+            | Mode::Wildlife
+            | Mode::Rotten
+            | Mode::TimeAttack(_)
+            | Mode::Daily
+            | Mode::Online
+            | Mode::Lockstep => 1,
+            Mode::LocalVersus => 2,
+            Mode::VersusAi(opponents) => opponents + 1,
+        }
+    }
+
+    fn rules(self) -> Rules {
+        match self {
+            Mode::Zen => Rules::ZEN,
+            Mode::Portals => Rules {
+                portals: PORTAL_PAIRS,
+                ..Rules::default()
+            },
+            Mode::Hazards => Rules {
+                hazards: HAZARD_COUNT,
+                ..Rules::default()
+            },
+            Mode::Wildlife => Rules {
+                moving_food: true,
+                ..Rules::default()
+            },
+            Mode::Rotten => Rules {
+                rot_ticks: Some(ROT_TICKS),
+                ..Rules::default()
+            },
+            Mode::TimeAttack(seconds) => Rules {
+                time_limit: Some(seconds * 1000 / TICK_INTERVAL as usize),
+                ..Rules::default()
+            },
+            // The computer players only play against player one.
+            Mode::VersusAi(_) => Rules {
+                ends_with_player_one: true,
+                ..Rules::default()
+            },
+            _ => Rules::default(),
+        }
+    }
+}
+
+fn is_bot(player: usize) -> bool {
+    BOTS.with(|bots| bots.borrow().iter().any(|(bot, _)| *bot == player))
+}
+
+fn is_demo() -> bool {
+    is_bot(0)
+}
+
+/// Whether the live game is the player's own solo game, the only kind that
+/// is saved to pick back up later. The daily challenge isn't one of them, as
+/// it only lasts the day.
+fn is_own_solo_game() -> bool {
+    matches!(
+        MODE.with(Cell::get),
+        Mode::Solo
+            | Mode::Zen
+            | Mode::Portals
+            | Mode::Hazards
+            | Mode::Wildlife
+            | Mode::Rotten
+            | Mode::TimeAttack(_)
+    ) && !is_demo()
+}
+
+/// Stores the player's solo game so it can be continued once the page is
+/// back. Finished games are never kept, so reloading can't undo a death.
+fn save_live_game() {
+    if !is_own_solo_game() || UNRANKED.with(Cell::get) {
+        return;
+    }
+    GAME.with(|game| {
+        let game = game.borrow();
+        let _ = if game.finished {
+            clear_game_state()
+        } else {
+            save_game_state(&game, &MODE.with(Cell::get).name())
+        };
+    });
+}
+
+/// Starts a new game of the current mode, or a demo played by `autopilot`
+/// if given.
+fn restart_game(autopilot: Option<Box<dyn Strategy>>) {
+    let mode = MODE.with(Cell::get);
+    leave_online();
+    if matches!(mode, Mode::Online | Mode::Lockstep) && autopilot.is_none() {
+        // Online games run on their own clock, so the local game stays paused.
+        stop_ticking();
+        BOTS.with(|bots| bots.borrow_mut().clear());
+        join_online(mode == Mode::Lockstep, |prediction| {
+            RENDERER.with(|renderer| renderer.borrow_mut().render(prediction));
         });
+        return;
+    }
+    let players = if autopilot.is_some() {
+        1
+    } else {
+        mode.players()
+    };
+    let bots = match (autopilot, mode) {
+        (Some(autopilot), _) => vec![(0, autopilot)],
+        (None, Mode::VersusAi(opponents)) => {
+            let strategy = DIFFICULTY.with(Cell::get).strategy();
+            (1..=opponents)
+                .map(|player| (player, strategy.build()))
+                .collect()
+        }
+        (None, _) => vec![],
+    };
+    BOTS.with(|cell| *cell.borrow_mut() = bots);
+    RECORD_BROKEN.with(|broken| broken.set(false));
+    UNRANKED.with(|unranked| unranked.set(false));
+    // Demos get a board of their own, so they give nothing of the
+    // challenge away.
+    let challenge = (mode == Mode::Daily && !is_demo()).then(Challenge::today);
+    CHALLENGE.with(|cell| cell.set(challenge));
+    let high_score = GAME_DATA.with(|data| high_score(&data.borrow(), mode));
+    RENDERER.with(|renderer| renderer.borrow_mut().high_score = high_score);
+    GAME.with(|game| {
+        let game = &mut *game.borrow_mut();
+        *game = match challenge {
+            Some(challenge) => SnakeGame::with_seed(
+                GAME_WIDTH,
+                GAME_HEIGHT,
+                challenge.food_count,
+                challenge.seed,
+            )
+            .with_rules(challenge.rules),
+            None => {
+                SnakeGame::with_players(GAME_WIDTH, GAME_HEIGHT, FOOD_COUNT, players, random_seed())
+                    .with_rules(mode.rules())
+            }
+        };
+        render_game(game);
+    });
+    start_ticking();
+}
+
+/// Advances the game by one tick and reacts to what happened in it.
+fn tick_game(game: &mut SnakeGame, renderer: &mut Renderer) {
+    BOTS.with(|bots| {
+        for (player, strategy) in bots.borrow_mut().iter_mut() {
+            let direction = strategy.next_direction(&game.view(*player));
+            game.change_direction(*player, direction);
+        }
+    });
+    let events = game.tick();
+    // Demo games don't count towards the player's records.
+    if is_demo() {
+        renderer.render(&*game);
+        return;
+    }
+    for event in &events {
+        if event.player().is_none_or(|player| !is_bot(player)) {
+            play_event(event, game.ticks);
+        }
+    }
+    match MODE.with(Cell::get) {
+        Mode::Solo | Mode::Portals | Mode::Hazards | Mode::Wildlife | Mode::Rotten => {}
+        Mode::Zen => {
+            if events
+                .iter()
+                .any(|event| matches!(event, GameEvent::FilledBoard { .. }))
+            {
+                alert("You filled the board, you win!");
+            }
+            renderer.render(&*game);
+            return;
+        }
+        Mode::Daily => {
+            if game.finished {
+                if let Some(challenge) = CHALLENGE.with(Cell::get) {
+                    finish_daily(&challenge, game, renderer);
+                }
+            }
+            renderer.render(&*game);
+            return;
+        }
+        mode @ Mode::TimeAttack(_) => {
+            let score = game.score(0);
+            record_high_score(renderer, mode, score);
+            renderer.render(&*game);
+            for event in events {
+                match event {
+                    GameEvent::Died { .. } => alert("You're a loser!"),
+                    GameEvent::FilledBoard { .. } => alert("You filled the board, you win!"),
+                    GameEvent::TimeUp => alert(&format!("Time's up! You scored {}", score)),
+                    _ => {}
+                }
+            }
+            return;
+        }
+        mode => {
+            renderer.render(&*game);
+            if let Some(outcome) = game.outcome() {
+                report_outcome(mode, outcome);
+            }
+            return;
+        }
+    }
+    let mode = MODE.with(Cell::get);
+    let score = game.score(0);
+    let ranked = !UNRANKED.with(Cell::get);
+    for event in events {
+        match event {
+            GameEvent::Ate { .. } if score.is_multiple_of(LEVEL_LENGTH) => {
+                play_sound(Sound::LevelUp);
+            }
+            GameEvent::Died { cause, .. } => {
+                if ranked {
+                    record_solo_game(game, mode, Some(cause));
+                }
+                alert("You're a loser!");
+            }
+            GameEvent::FilledBoard { .. } => {
+                if ranked {
+                    record_solo_game(game, mode, None);
+                }
+                alert("You filled the board, you win!");
+            }
+            _ => {}
+        }
+    }
+    if ranked {
+        record_high_score(renderer, mode, score);
+    }
+    // The best run was played on a classic board, which is the only one it
+    // can be raced on.
+    if mode != Mode::Solo {
+        renderer.render(&*game);
+        return;
+    }
+    GAME_DATA.with(|data| {
+        renderer.render(&GhostRace {
+            game,
+            ghost: data.borrow().best_ghost.as_ref(),
+        })
+    });
+}
+
+/// The high score of `mode`, which only solo modes have.
+fn high_score(data: &GameData, mode: Mode) -> usize {
+    match mode {
+        Mode::TimeAttack(seconds) => data.time_attack.get(&seconds).copied().unwrap_or(0),
+        Mode::Portals | Mode::Hazards | Mode::Wildlife | Mode::Rotten => data
+            .mode_high_scores
+            .get(&mode.name())
+            .copied()
+            .unwrap_or(0),
+        Mode::Daily => CHALLENGE
+            .with(Cell::get)
+            .and_then(|challenge| data.daily.best_on(challenge.day))
+            .unwrap_or(0),
+        _ => data.high_score,
+    }
+}
+
+/// Makes `score` the high score of `mode` if it beats it.
+fn record_high_score(renderer: &mut Renderer, mode: Mode, score: usize) {
+    if score <= renderer.high_score {
+        return;
+    }
+    // Chime once, when the record from before this game falls.
+    if renderer.high_score > 0 && !RECORD_BROKEN.with(|broken| broken.replace(true)) {
+        play_sound(Sound::HighScore);
+    }
+    renderer.high_score = score;
+    GAME_DATA.with(|data| {
+        let data = &mut *data.borrow_mut();
+        match mode {
+            Mode::TimeAttack(seconds) => {
+                data.time_attack.insert(seconds, score);
+            }
+            Mode::Portals | Mode::Hazards | Mode::Wildlife | Mode::Rotten => {
+                data.mode_high_scores.insert(mode.name(), score);
+            }
+            _ => data.high_score = score,
+        }
+        let _ = save_game_data(data);
     });
+}
+
+/// Records the daily challenge game that just ended and offers to copy a
+/// summary of it to share.
+fn finish_daily(challenge: &Challenge, game: &SnakeGame, renderer: &mut Renderer) {
+    let score = game.score(0);
+    let streak = GAME_DATA.with(|data| {
+        let data = &mut *data.borrow_mut();
+        data.daily.record(challenge.day, score);
+        let _ = save_game_data(data);
+        renderer.high_score = high_score(data, Mode::Daily);
+        data.daily.streak
+    });
+    let text = share_text(challenge, score, game.snakes[0].body.len(), streak);
+    let share = window()
+        .unwrap_throw()
+        .confirm_with_message(&format!("{}\n\nCopy this to share it?", text))
+        .unwrap_or(false);
+    if share {
+        copy_to_clipboard(&text);
+    }
+}
+
+/// Saves how the solo game that just ended went. `cause` is `None` if the
+/// snake filled the board.
+fn record_solo_game(game: &SnakeGame, mode: Mode, cause: Option<DeathCause>) {
+    GAME_DATA.with(|data| {
+        let data = &mut *data.borrow_mut();
+        data.stats.record_game(game, cause);
+        // Runs under other rules aren't raced or compared against classic
+        // ones.
+        if mode == Mode::Solo {
+            data.record_replay(game.replay());
+            data.record_ghost(game.ghost());
+        }
+        let _ = save_game_data(data);
+    });
+    let _ = clear_game_state();
+}
+
+fn report_outcome(mode: Mode, outcome: Outcome) {
+    let Mode::VersusAi(_) = mode else {
+        match outcome {
+            Outcome::Won(player) => alert(&format!("Player {} wins!", player + 1)),
+            Outcome::Draw => alert("It's a draw!"),
+        }
+        return;
+    };
+    GAME_DATA.with(|data| {
+        let data = &mut *data.borrow_mut();
+        let record = data
+            .versus_ai
+            .entry(DIFFICULTY.with(Cell::get))
+            .or_default();
+        match outcome {
+            Outcome::Won(0) => record.wins += 1,
+            Outcome::Won(_) => record.losses += 1,
+            Outcome::Draw => record.draws += 1,
+        }
+        let _ = save_game_data(data);
+    });
+    alert(match outcome {
+        Outcome::Won(0) => "You win!",
+        Outcome::Won(_) => "You're a loser!",
+        Outcome::Draw => "It's a draw!",
+    });
+}
+
+fn render_game(game: &SnakeGame) {
+    RENDERER.with(|renderer| {
+        renderer.borrow_mut().render(game);
+    });
+}
+
+#[wasm_bindgen(start)]
+fn main() {
+    // Offer to carry on with the game that was going when the page was left,
+    // paused until the player steers. Games saved before the mode was kept
+    // with them were all solo games.
+    let resumed = load_game_state()
+        .ok()
+        .and_then(|(game, mode)| {
+            let mode = match mode {
+                Some(name) => Mode::from_name(&name)?,
+                None => Mode::Solo,
+            };
+            Some((game, mode))
+        })
+        .filter(|(game, _)| !game.finished)
+        .filter(|_| {
+            window()
+                .unwrap_throw()
+                .confirm_with_message("Continue last game?")
+                .unwrap_or(false)
+        });
+    let _ = clear_game_state();
+    match resumed {
+        Some((resumed, mode)) => {
+            MODE.with(|cell| cell.set(mode));
+            let high_score = GAME_DATA.with(|data| high_score(&data.borrow(), mode));
+            RENDERER.with(|renderer| renderer.borrow_mut().high_score = high_score);
+            GAME.with(|game| {
+                *game.borrow_mut() = resumed;
+                render_game(&game.borrow());
+            });
+        }
+        None => start_ticking(),
+    }
     CONTROLLER.with(|_| {});
+    init_audio(GAME_DATA.with(|data| data.borrow().audio));
+    let onvisibilitychange = Closure::wrap(Box::new(|| {
+        let hidden = window()
+            .and_then(|window| window.document())
+            .is_some_and(|document| document.hidden());
+        if hidden {
+            save_live_game();
+        }
+    }) as Box<dyn FnMut()>);
+    window()
+        .unwrap_throw()
+        .document()
+        .unwrap_throw()
+        .set_onvisibilitychange(onvisibilitychange.as_ref().dyn_ref());
+    onvisibilitychange.forget();
+    // Mobile browsers don't always report the page as hidden before closing
+    // it, but do send `pagehide`.
+    let onpagehide = Closure::wrap(Box::new(save_live_game) as Box<dyn FnMut()>);
+    window()
+        .unwrap_throw()
+        .set_onpagehide(onpagehide.as_ref().dyn_ref());
+    onpagehide.forget();
+    add_menu_button("stats", |_| {
+        GAME_DATA.with(|data| show_stats_screen(&data.borrow()));
+    });
+    add_menu_button("sound", |_| {
+        show_sound_settings(|settings| {
+            GAME_DATA.with(|data| {
+                let data = &mut *data.borrow_mut();
+                data.audio = settings;
+                let _ = save_game_data(data);
+            });
+        });
+    });
+    add_menu_button("export", |_| {
+        GAME_DATA.with(|data| {
+            if let Err(e) = export_game_data(&data.borrow()) {
+                alert(&format!("Could not export save: {:?}", e));
+            }
+        });
+    });
+    add_menu_button("import", |_| {
+        import_game_data(|result| match result {
+            Ok(imported) => GAME_DATA.with(|data| {
+                let data = &mut *data.borrow_mut();
+                data.merge(imported);
+                let _ = save_game_data(data);
+                let high_score = high_score(data, MODE.with(Cell::get));
+                RENDERER.with(|renderer| renderer.borrow_mut().high_score = high_score);
+            }),
+            Err(e) => alert(&format!("Invalid save file: {}", e)),
+        });
+    });
+    add_menu_button("autopilot: off", |button| {
+        if is_playing_back() {
+            return;
+        }
+        let label = button.text_content().unwrap_or_default();
+        let current = label.trim_start_matches("autopilot: ");
+        let next = match StrategyKind::from_name(current) {
+            None => StrategyKind::ALL.first(),
+            Some(kind) => StrategyKind::ALL
+                .iter()
+                .skip_while(|other| **other != kind)
+                .nth(1),
+        };
+        button.set_text_content(Some(&format!(
+            "autopilot: {}",
+            next.map_or("off", |kind| kind.name())
+        )));
+        restart_game(next.map(|kind| kind.build()));
+    })
+    .set_id("autopilot-button");
+    add_menu_button(
+        &format!("mode: {}", MODE.with(Cell::get).name()),
+        |button| {
+            if is_playing_back() {
+                return;
+            }
+            let mode = MODE.with(|mode| {
+                let current = Mode::ALL.iter().position(|other| *other == mode.get());
+                mode.set(Mode::ALL[current.map_or(0, |i| (i + 1) % Mode::ALL.len())]);
+                mode.get()
+            });
+            button.set_text_content(Some(&format!("mode: {}", mode.name())));
+            if let Some(autopilot) = window()
+                .and_then(|window| window.document())
+                .and_then(|document| document.get_element_by_id("autopilot-button"))
+            {
+                autopilot.set_text_content(Some("autopilot: off"));
+            }
+            restart_game(None);
+        },
+    );
+    add_menu_button("ai: normal", |button| {
+        let difficulty = DIFFICULTY.with(|difficulty| {
+            let current = Difficulty::ALL
+                .iter()
+                .position(|other| *other == difficulty.get());
+            difficulty.set(Difficulty::ALL[current.map_or(0, |i| (i + 1) % Difficulty::ALL.len())]);
+            difficulty.get()
+        });
+        button.set_text_content(Some(&format!("ai: {}", difficulty.name())));
+        if !is_playing_back() && matches!(MODE.with(Cell::get), Mode::VersusAi(_)) && !is_demo() {
+            restart_game(None);
+        }
+    });
+    add_menu_button("state", |_| {
+        if is_playing_back() || is_online() || !is_own_solo_game() {
+            return;
+        }
+        let json = GAME.with(|game| serde_json::to_string(&*game.borrow()).unwrap_throw());
+        let Some(pasted) = window()
+            .unwrap_throw()
+            .prompt_with_message_and_default("Game state (paste another one to load it)", &json)
+            .unwrap_throw()
+        else {
+            return;
+        };
+        if pasted == json {
+            return;
+        }
+        match SnakeGame::from_json(&pasted) {
+            Ok(pasted) => {
+                stop_ticking();
+                // A pasted game could be set up to be anything, so nothing
+                // it scores counts, and it isn't kept to resume later.
+                UNRANKED.with(|unranked| unranked.set(true));
+                let _ = clear_game_state();
+                GAME.with(|game| {
+                    *game.borrow_mut() = pasted;
+                    render_game(&game.borrow());
+                });
+            }
+            Err(e) => alert(&format!("Invalid game state: {}", e)),
+        }
+    });
+    add_menu_button("replay", |_| {
+        if is_playing_back() || is_online() {
+            return;
+        }
+        let Some(replay) = GAME_DATA.with(|data| data.borrow().best_replay.clone()) else {
+            alert("No replay recorded yet");
+            return;
+        };
+        stop_ticking();
+        start_playback(replay, render_game, || {
+            GAME.with(|game| {
+                let game = game.borrow();
+                render_game(&game);
+                if !game.finished {
+                    start_ticking();
+                }
+            });
+        });
+    });
 }
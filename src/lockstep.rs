@@ -0,0 +1,277 @@
+use crate::net::{PeerMessage, HASH_INTERVAL};
+use crate::snake::{Direction, GameEvent, SnakeGame, MAX_QUEUED_DIRECTIONS};
+use crate::{FOOD_COUNT, GAME_HEIGHT, GAME_WIDTH};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Ticks a turn can be held back for in lockstep games.
+pub const INPUT_DELAYS: [usize; 5] = [1, 2, 3, 4, 5];
+
+/// One player's copy of a lockstep game. Every player runs the same seeded
+/// game and only moves it on once everybody's input for the next tick is in,
+/// so the games never need correcting.
+pub struct Lockstep {
+    pub game: SnakeGame,
+    pub player: usize,
+    /// Ticks between a turn being made and applied, which gives it time to
+    /// reach the other players before they need it.
+    input_delay: usize,
+    /// Ticks played so far.
+    tick: usize,
+    /// Every player's inputs from the current tick on.
+    inputs: Vec<VecDeque<Option<Direction>>>,
+    /// The local player's turns still to be sent, one per tick.
+    turns: VecDeque<Direction>,
+    /// The tick from which each player who left is out of the game.
+    left: Vec<Option<usize>>,
+    /// The first hash seen for every hashed tick, ours or another player's.
+    hashes: BTreeMap<usize, u64>,
+    /// The tick after which the games turned out to differ.
+    pub desync: Option<usize>,
+}
+
+impl Lockstep {
+    /// Creates our copy of the game, along with the messages to send the
+    /// other players before the first tick.
+    pub fn new(
+        seed: u64,
+        players: usize,
+        player: usize,
+        input_delay: usize,
+    ) -> (Self, Vec<PeerMessage>) {
+        let mut inputs = vec![VecDeque::new(); players];
+        // Nobody can have turned before the first ticks of the delay.
+        inputs[player].extend(vec![None; input_delay]);
+        let lockstep = Self {
+            game: SnakeGame::with_players(GAME_WIDTH, GAME_HEIGHT, FOOD_COUNT, players, seed),
+            player,
+            input_delay,
+            tick: 0,
+            inputs,
+            turns: VecDeque::new(),
+            left: vec![None; players],
+            hashes: BTreeMap::new(),
+            desync: None,
+        };
+        let messages = (0..input_delay)
+            .map(|tick| PeerMessage::Input {
+                tick,
+                direction: None,
+            })
+            .collect();
+        (lockstep, messages)
+    }
+
+    /// Queues a turn of the local player.
+    pub fn turn(&mut self, direction: Direction) {
+        if self.turns.len() < MAX_QUEUED_DIRECTIONS {
+            self.turns.push_back(direction);
+        }
+    }
+
+    /// The local player's inputs the game hasn't been given yet, one for each
+    /// tick from the next one on: those held back by the input delay, then
+    /// the turns still to be sent.
+    pub fn upcoming(&self) -> Vec<Option<Direction>> {
+        self.inputs[self.player]
+            .iter()
+            .copied()
+            .chain(self.turns.iter().copied().map(Some))
+            .collect()
+    }
+
+    fn is_out(&self, player: usize) -> bool {
+        self.left[player].is_some_and(|tick| tick <= self.tick)
+    }
+
+    /// Players whose input for the next tick hasn't arrived yet.
+    pub fn waiting_for(&self) -> Vec<usize> {
+        (0..self.inputs.len())
+            .filter(|player| !self.is_out(*player) && self.inputs[*player].is_empty())
+            .collect()
+    }
+
+    pub fn receive(&mut self, from: usize, message: PeerMessage) {
+        match message {
+            PeerMessage::Input { tick, direction } => {
+                let Some(inputs) = self.inputs.get_mut(from) else {
+                    return;
+                };
+                // The relay keeps every player's messages in order, so
+                // anything else means the games can't stay in step.
+                if tick != self.tick + inputs.len() {
+                    self.desync.get_or_insert(self.tick);
+                    return;
+                }
+                inputs.push_back(direction);
+            }
+            PeerMessage::Hash { tick, hash } => self.check_hash(tick, hash),
+        }
+    }
+
+    /// Takes a player who disconnected out of the game, right after the last
+    /// tick they sent an input for. Everybody got the same inputs from them,
+    /// so everybody takes them out on the same tick.
+    pub fn leave(&mut self, player: usize) {
+        if let Some(inputs) = self.inputs.get(player) {
+            self.left[player] = Some(self.tick + inputs.len());
+        }
+    }
+
+    fn check_hash(&mut self, tick: usize, hash: u64) {
+        if *self.hashes.entry(tick).or_insert(hash) != hash {
+            self.desync.get_or_insert(tick);
+        }
+    }
+
+    /// Plays the next tick if everybody's input for it is in. Returns what
+    /// happened in it and the messages to send the other players.
+    pub fn advance(&mut self) -> Option<(Vec<GameEvent>, Vec<PeerMessage>)> {
+        if self.game.finished || self.desync.is_some() || !self.waiting_for().is_empty() {
+            return None;
+        }
+        let mut messages = vec![];
+        let direction = self.turns.pop_front();
+        self.inputs[self.player].push_back(direction);
+        messages.push(PeerMessage::Input {
+            tick: self.tick + self.input_delay,
+            direction,
+        });
+        for player in 0..self.inputs.len() {
+            if self.is_out(player) {
+                self.game.forfeit(player);
+            } else if let Some(direction) = self.inputs[player].pop_front().flatten() {
+                self.game.change_direction(player, direction);
+            }
+        }
+        let events = self.game.tick();
+        self.tick += 1;
+        if self.tick.is_multiple_of(HASH_INTERVAL) {
+            let hash = self.game.state_hash();
+            self.check_hash(self.tick, hash);
+            messages.push(PeerMessage::Hash {
+                tick: self.tick,
+                hash,
+            });
+        }
+        Some((events, messages))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands `messages` from `from` to every other player, as the relay does.
+    fn relay(peers: &mut [Lockstep], from: usize, messages: Vec<PeerMessage>) {
+        for message in messages {
+            for (to, peer) in peers.iter_mut().enumerate() {
+                if to != from {
+                    peer.receive(from, message);
+                }
+            }
+        }
+    }
+
+    /// Every player's copy of a new game, with the opening messages passed
+    /// around.
+    fn start(players: usize, input_delay: usize) -> Vec<Lockstep> {
+        let (mut peers, opening): (Vec<_>, Vec<_>) = (0..players)
+            .map(|player| Lockstep::new(7, players, player, input_delay))
+            .unzip();
+        for (from, messages) in opening.into_iter().enumerate() {
+            relay(&mut peers, from, messages);
+        }
+        peers
+    }
+
+    /// Lets every player play a tick, then passes around what they sent.
+    fn round(peers: &mut [Lockstep]) {
+        let sent = peers
+            .iter_mut()
+            .map(|peer| peer.advance().map(|(_, messages)| messages))
+            .collect::<Vec<_>>();
+        for (from, messages) in sent.into_iter().enumerate() {
+            relay(peers, from, messages.unwrap_or_default());
+        }
+    }
+
+    #[test]
+    fn every_copy_plays_the_same_game() {
+        for input_delay in INPUT_DELAYS {
+            let mut peers = start(3, input_delay);
+            for tick in 0..200 {
+                for (player, peer) in peers.iter_mut().enumerate() {
+                    if tick % (5 + player) == 0 {
+                        peer.turn(Direction::ALL[(tick / 5 + player) % 4]);
+                    }
+                }
+                round(&mut peers);
+            }
+            let hash = peers[0].game.state_hash();
+            for peer in &peers {
+                assert_eq!(peer.desync, None, "input delay {}", input_delay);
+                assert_eq!(peer.game.ticks, peers[0].game.ticks);
+                assert_eq!(peer.game.state_hash(), hash, "input delay {}", input_delay);
+            }
+        }
+    }
+
+    #[test]
+    fn turns_are_applied_after_the_input_delay() {
+        let mut peers = start(2, 3);
+        peers[0].turn(Direction::Up);
+        assert_eq!(
+            peers[0].upcoming(),
+            vec![None, None, None, Some(Direction::Up)]
+        );
+        for _ in 0..3 {
+            round(&mut peers);
+            assert_ne!(peers[1].game.snakes[0].direction(), Direction::Up);
+        }
+        round(&mut peers);
+        assert_eq!(peers[0].game.snakes[0].direction(), Direction::Up);
+        assert_eq!(peers[1].game.snakes[0].direction(), Direction::Up);
+    }
+
+    #[test]
+    fn nobody_moves_on_without_everybody_else() {
+        let mut peers = start(2, 1);
+        assert!(peers[0].advance().is_some());
+        assert_eq!(peers[0].waiting_for(), vec![1]);
+        assert!(peers[0].advance().is_none());
+        assert_eq!(peers[0].game.ticks, 1);
+    }
+
+    #[test]
+    fn inputs_out_of_order_are_a_desync() {
+        let mut peers = start(2, 1);
+        peers[0].receive(
+            1,
+            PeerMessage::Input {
+                tick: 2,
+                direction: None,
+            },
+        );
+        assert_eq!(peers[0].desync, Some(0));
+        assert!(peers[0].advance().is_none());
+    }
+
+    #[test]
+    fn different_hashes_are_a_desync() {
+        let mut peers = start(2, 1);
+        for _ in 0..HASH_INTERVAL {
+            round(&mut peers);
+        }
+        assert_eq!(peers[0].desync, None);
+        let hash = peers[1].game.state_hash() ^ 1;
+        peers[0].receive(
+            1,
+            PeerMessage::Hash {
+                tick: HASH_INTERVAL,
+                hash,
+            },
+        );
+        assert_eq!(peers[0].desync, Some(HASH_INTERVAL));
+        assert!(peers[0].advance().is_none());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::helper::{onclick, style};
+use wasm_bindgen::prelude::*;
+use web_sys::{window, HtmlButtonElement, HtmlElement};
+
+fn menu_root() -> HtmlElement {
+    let window = window().unwrap_throw();
+    let document = window.document().unwrap_throw();
+    match document.get_element_by_id("menu-root") {
+        Some(root) => root.dyn_into().unwrap_throw(),
+        None => {
+            let root = document
+                .create_element("div")
+                .unwrap_throw()
+                .dyn_into::<HtmlElement>()
+                .unwrap_throw();
+            root.set_id("menu-root");
+            style!(root {
+                "position": "absolute";
+                "top": "8px";
+                "right": "8px";
+                "display": "flex";
+                "gap": "4px";
+            });
+            document
+                .body()
+                .unwrap_throw()
+                .append_child(&root)
+                .unwrap_throw();
+            root
+        }
+    }
+}
+
+/// Adds a button to the menu. `onclick` is given the button, so it can
+/// update its own label.
+pub fn add_menu_button(
+    label: &str,
+    mut onclick: impl FnMut(&HtmlButtonElement) + 'static,
+) -> HtmlButtonElement {
+    let window = window().unwrap_throw();
+    let document = window.document().unwrap_throw();
+    let button = document
+        .create_element("button")
+        .unwrap_throw()
+        .dyn_into::<HtmlButtonElement>()
+        .unwrap_throw();
+    button.set_text_content(Some(label));
+    onclick!(button -> {
+        let button = button.clone();
+        move || onclick(&button)
+    });
+    menu_root().append_child(&button).unwrap_throw();
+    button
+}
@@ -0,0 +1,324 @@
+//! Messages exchanged between the online client and the server in
+//! `src/bin/server`.
+//!
+//! Every message is a JSON text frame. In rooms the server plays, it sends a
+//! full [`Snapshot`] when a game starts, every [`KEYFRAME_INTERVAL`] ticks and
+//! whenever a client asks for one; every other tick it only sends the
+//! [`Delta`] to the last one. In lockstep rooms it only passes
+//! [`PeerMessage`]s between the players, who each run the game themselves.
+
+use crate::helper::Vector;
+use crate::snake::{Direction, FoodKind, Outcome, MAX_CELLS};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_PORT: u16 = 9001;
+pub const MAX_PLAYERS: usize = 4;
+/// Ticks between two full snapshots of a running game.
+pub const KEYFRAME_INTERVAL: usize = 50;
+/// Ticks between two state hashes lockstep players compare.
+pub const HASH_INTERVAL: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Opens a new room and joins it as its host. The server plays the
+    /// room's games unless `lockstep` is set.
+    Create {
+        #[serde(default)]
+        lockstep: bool,
+    },
+    Join {
+        code: String,
+    },
+    /// Starts a game with everyone in the room. Only the host may.
+    Start,
+    /// A direction change, numbered so the client can tell once the server
+    /// has seen it.
+    Input {
+        seq: u32,
+        direction: Direction,
+    },
+    /// Asks for a full snapshot after missing a delta.
+    Resync,
+    /// A message for every other player in a lockstep game.
+    Relay {
+        message: PeerMessage,
+    },
+    /// Ends a lockstep game, once the sender's copy of it is over.
+    Finished,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// The room's line-up changed. `you` is the receiver's place in it, which
+    /// is also their player number in the next game; the first is the host.
+    Lobby {
+        code: String,
+        lockstep: bool,
+        players: usize,
+        you: usize,
+    },
+    /// `acked` is the last input of the receiver the server has queued.
+    Snapshot {
+        you: usize,
+        acked: u32,
+        snapshot: Snapshot,
+    },
+    Delta {
+        acked: u32,
+        delta: Delta,
+    },
+    /// `outcome` is `None` if the game had a single player.
+    GameOver {
+        outcome: Option<Outcome>,
+        snapshot: Snapshot,
+    },
+    Error {
+        message: String,
+    },
+    /// A lockstep game started. Every player creates the same game from
+    /// `seed`.
+    Started {
+        you: usize,
+        players: usize,
+        seed: u64,
+    },
+    Relay {
+        from: usize,
+        message: PeerMessage,
+    },
+    /// A player of the running lockstep game disconnected.
+    Left {
+        player: usize,
+    },
+}
+
+/// What lockstep players tell each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PeerMessage {
+    /// The sender's turn, if any, to apply before `tick`. Every player sends
+    /// one for every tick, since nobody can move on without it.
+    Input {
+        tick: usize,
+        direction: Option<Direction>,
+    },
+    /// The sender's [`SnakeGame::state_hash`](crate::snake::SnakeGame::state_hash)
+    /// after `tick`.
+    Hash { tick: usize, hash: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnakeState {
+    /// Head first.
+    pub body: Vec<Vector>,
+    pub direction: Direction,
+    /// Direction changes still to be applied, one per tick.
+    pub queue: Vec<Direction>,
+    pub alive: bool,
+}
+
+/// Everything a client needs to draw a game.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tick: usize,
+    pub width: usize,
+    pub height: usize,
+    pub snakes: Vec<SnakeState>,
+    pub foods: Vec<(Vector, FoodKind)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnakeDelta {
+    /// The snake's new head, if it moved.
+    pub head: Option<Vector>,
+    pub length: usize,
+    pub direction: Direction,
+    pub queue: Vec<Direction>,
+    pub alive: bool,
+}
+
+/// The changes between two snapshots one tick apart.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delta {
+    /// Tick of the snapshot the delta leads to.
+    pub tick: usize,
+    pub snakes: Vec<SnakeDelta>,
+    pub eaten: Vec<Vector>,
+    pub spawned: Vec<(Vector, FoodKind)>,
+}
+
+impl Snapshot {
+    /// Whether the snapshot is a game a client can draw: a board that isn't
+    /// empty or too big, at least one snake, every snake with a head, and
+    /// every cell on the board.
+    pub fn is_valid(&self) -> bool {
+        let on_board = |pos: &Vector| pos.index_in(self.width, self.height).is_some();
+        self.width > 0
+            && self.height > 0
+            && self
+                .width
+                .checked_mul(self.height)
+                .is_some_and(|cells| cells <= MAX_CELLS)
+            && !self.snakes.is_empty()
+            && self
+                .snakes
+                .iter()
+                .all(|snake| !snake.body.is_empty() && snake.body.iter().all(on_board))
+            && self.foods.iter().all(|(pos, _)| on_board(pos))
+    }
+
+    /// The delta that turns this snapshot into `next`, the one a tick later.
+    pub fn delta_to(&self, next: &Snapshot) -> Delta {
+        Delta {
+            tick: next.tick,
+            snakes: self
+                .snakes
+                .iter()
+                .zip(&next.snakes)
+                .map(|(before, after)| SnakeDelta {
+                    head: (after.body[0] != before.body[0]).then_some(after.body[0]),
+                    length: after.body.len(),
+                    direction: after.direction,
+                    queue: after.queue.clone(),
+                    alive: after.alive,
+                })
+                .collect(),
+            eaten: self
+                .foods
+                .iter()
+                .filter(|food| !next.foods.contains(food))
+                .map(|(pos, _)| *pos)
+                .collect(),
+            spawned: next
+                .foods
+                .iter()
+                .filter(|food| !self.foods.contains(food))
+                .copied()
+                .collect(),
+        }
+    }
+
+    /// Applies `delta`. Returns false, leaving the snapshot as it was, if the
+    /// delta doesn't follow on from it or would leave it invalid.
+    pub fn apply(&mut self, delta: &Delta) -> bool {
+        if delta.tick != self.tick + 1 || delta.snakes.len() != self.snakes.len() {
+            return false;
+        }
+        let mut next = self.clone();
+        next.tick = delta.tick;
+        for (snake, change) in next.snakes.iter_mut().zip(&delta.snakes) {
+            if let Some(head) = change.head {
+                snake.body.insert(0, head);
+            }
+            snake.body.truncate(change.length);
+            snake.direction = change.direction;
+            snake.queue = change.queue.clone();
+            snake.alive = change.alive;
+        }
+        next.foods.retain(|(pos, _)| !delta.eaten.contains(pos));
+        next.foods.extend(&delta.spawned);
+        if !next.is_valid() {
+            return false;
+        }
+        *self = next;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::StrategyKind;
+    use crate::snake::{Rules, SnakeGame};
+
+    /// Snapshots of every tick of a four player game between computer
+    /// players, up to the end, which the server sends whole.
+    fn snapshots(rules: Rules, seed: u64) -> Vec<Snapshot> {
+        let mut game = SnakeGame::with_players(20, 20, 8, 4, seed).with_rules(rules);
+        let mut strategies = (0..4)
+            .map(|player| StrategyKind::Wanderer.build_with_seed(seed + player))
+            .collect::<Vec<_>>();
+        let mut snapshots = vec![game.snapshot()];
+        while !game.finished && game.ticks < 500 {
+            for (player, strategy) in strategies.iter_mut().enumerate() {
+                if game.snakes[player].alive {
+                    let direction = strategy.next_direction(&game.view(player));
+                    game.change_direction(player, direction);
+                }
+            }
+            game.tick();
+            if game.finished {
+                break;
+            }
+            snapshots.push(game.snapshot());
+        }
+        snapshots
+    }
+
+    #[test]
+    fn deltas_rebuild_every_snapshot() {
+        let rules = Rules {
+            moving_food: true,
+            ..Rules::default()
+        };
+        // Moved food is eaten and spawned again as far as deltas go, so it
+        // can end up in another place in the list, which nothing minds.
+        let sorted = |snapshot: &Snapshot| {
+            let mut snapshot = snapshot.clone();
+            snapshot.foods.sort_by_key(|(pos, _)| (pos.0, pos.1));
+            snapshot
+        };
+        for seed in 0..5 {
+            let snapshots = snapshots(rules, seed);
+            let mut client = snapshots[0].clone();
+            for pair in snapshots.windows(2) {
+                let delta = pair[0].delta_to(&pair[1]);
+                // Deltas reach clients as JSON.
+                let json = serde_json::to_string(&delta).unwrap();
+                let delta = serde_json::from_str::<Delta>(&json).unwrap();
+                assert!(client.apply(&delta), "seed {}, tick {}", seed, delta.tick);
+                assert_eq!(
+                    sorted(&client),
+                    sorted(&pair[1]),
+                    "seed {}, tick {}",
+                    seed,
+                    delta.tick
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn apply_refuses_deltas_that_do_not_follow_on() {
+        let snapshots = snapshots(Rules::default(), 1);
+        let before = snapshots[1].clone();
+        let mut client = before.clone();
+        // A delta got lost on the way.
+        assert!(!client.apply(&snapshots[2].delta_to(&snapshots[3])));
+        assert_eq!(client, before);
+        // A delta from before the snapshot.
+        assert!(!client.apply(&snapshots[0].delta_to(&snapshots[1])));
+        assert_eq!(client, before);
+    }
+
+    #[test]
+    fn apply_refuses_deltas_that_leave_a_snapshot_invalid() {
+        let snapshots = snapshots(Rules::default(), 2);
+        let before = snapshots[0].clone();
+        let mut client = before.clone();
+        let mut headless = before.delta_to(&snapshots[1]);
+        headless.snakes[0].length = 0;
+        assert!(!client.apply(&headless));
+        assert_eq!(client, before);
+        let mut off_board = before.delta_to(&snapshots[1]);
+        off_board.spawned.push((Vector(-1, 0), FoodKind::Carrot));
+        assert!(!client.apply(&off_board));
+        assert_eq!(client, before);
+        let mut fewer_snakes = before.delta_to(&snapshots[1]);
+        fewer_snakes.snakes.pop();
+        assert!(!client.apply(&fewer_snakes));
+        assert_eq!(client, before);
+    }
+}
@@ -0,0 +1,494 @@
+use crate::helper::{onclick, style};
+use crate::lockstep::{Lockstep, INPUT_DELAYS};
+use crate::net::{ClientMessage, PeerMessage, ServerMessage, Snapshot, DEFAULT_PORT, MAX_PLAYERS};
+use crate::renderer::{Frame, Overlay, Renderable};
+use crate::snake::{alert, Direction, Outcome, SnakeGame};
+use crate::TICK_INTERVAL;
+use js_sys::Function;
+use std::cell::RefCell;
+use wasm_bindgen::prelude::*;
+use web_sys::{window, HtmlButtonElement, HtmlElement, MessageEvent, WebSocket};
+
+const PREDICTION_OPACITY: f64 = 0.4;
+/// Ticks an online game is played ahead at most, however slow the server is
+/// to answer.
+const MAX_PREDICTION_TICKS: usize = 5;
+
+/// An online game with the local snake drawn where it will be once the
+/// inputs the other players don't know about yet have been played.
+pub struct Prediction<'a> {
+    pub game: &'a SnakeGame,
+    pub player: usize,
+    /// The local player's inputs still to reach the game, one for each tick
+    /// to play ahead.
+    pub upcoming: Vec<Option<Direction>>,
+}
+
+impl Renderable for Prediction<'_> {
+    fn frame(&self) -> Frame {
+        let mut frame = self.game.frame();
+        let playing = self
+            .game
+            .snakes
+            .get(self.player)
+            .is_some_and(|snake| snake.alive);
+        if !playing || self.game.finished {
+            return frame;
+        }
+        let mut ahead = self.game.clone();
+        for input in &self.upcoming {
+            if let Some(direction) = input {
+                ahead.change_direction(self.player, *direction);
+            }
+            ahead.tick();
+        }
+        // A snake on its way to dying has nowhere to be drawn.
+        let snake = &ahead.snakes[self.player];
+        if !snake.alive {
+            return frame;
+        }
+        let predicted = ahead.frame();
+        for pos in &snake.body {
+            let cell = predicted.cell(pos);
+            if frame.cell(pos) != cell {
+                frame.overlays.push(Overlay {
+                    pos: *pos,
+                    cell,
+                    opacity: PREDICTION_OPACITY,
+                });
+            }
+        }
+        frame
+    }
+}
+
+fn outcome_message(outcome: Option<Outcome>, player: Option<usize>) -> String {
+    match outcome {
+        Some(Outcome::Won(winner)) if Some(winner) == player => "You win!".to_string(),
+        Some(Outcome::Won(winner)) => format!("Player {} wins!", winner + 1),
+        Some(Outcome::Draw) => "It's a draw!".to_string(),
+        None => "Game over!".to_string(),
+    }
+}
+
+struct Online {
+    socket: WebSocket,
+    /// Our player number while a game is running.
+    player: Option<usize>,
+    snapshot: Option<Snapshot>,
+    /// Whether a full snapshot has been asked for and not arrived yet.
+    resyncing: bool,
+    /// Direction changes the server hasn't confirmed yet, with the time
+    /// each was sent at.
+    pending: Vec<(u32, Direction, f64)>,
+    /// Milliseconds the server took to play the last input it confirmed.
+    round_trip: f64,
+    next_seq: u32,
+    /// Our copy of the running game in a lockstep room.
+    lockstep: Option<Lockstep>,
+    /// Index into [`INPUT_DELAYS`] used for the next lockstep game.
+    input_delay: usize,
+    interval_id: Option<i32>,
+    lobby: HtmlElement,
+    status: HtmlElement,
+    start_button: HtmlButtonElement,
+    delay_button: HtmlButtonElement,
+    onframe: Box<dyn FnMut(&Prediction)>,
+    _onopen: Closure<dyn FnMut()>,
+    _onmessage: Closure<dyn FnMut(MessageEvent)>,
+    _onclose: Closure<dyn FnMut()>,
+}
+
+thread_local! {
+    static ONLINE: RefCell<Option<Online>> = const { RefCell::new(None) };
+    static STEP_CLOSURE: Closure<dyn FnMut()> = Closure::wrap(Box::new(|| {
+        with_online(Online::step)
+    }) as Box<dyn FnMut()>);
+}
+
+impl Online {
+    fn send(&self, message: &ClientMessage) {
+        // Sending fails while the socket is still connecting or already
+        // closed; there is nothing to do about either.
+        let _ = self
+            .socket
+            .send_with_str(&serde_json::to_string(message).unwrap_throw());
+    }
+
+    fn confirm(&mut self, acked: u32) {
+        let now = js_sys::Date::now();
+        if let Some((_, _, sent)) = self.pending.iter().rfind(|(seq, _, _)| *seq <= acked) {
+            self.round_trip = now - sent;
+        }
+        self.pending.retain(|(seq, _, _)| *seq > acked);
+    }
+
+    fn relay(&self, messages: Vec<PeerMessage>) {
+        for message in messages {
+            self.send(&ClientMessage::Relay { message });
+        }
+    }
+
+    fn render(&mut self) {
+        if let Some(lockstep) = &self.lockstep {
+            (self.onframe)(&Prediction {
+                game: &lockstep.game,
+                player: lockstep.player,
+                upcoming: lockstep.upcoming(),
+            });
+            return;
+        }
+        let (Some(snapshot), Some(player)) = (&self.snapshot, self.player) else {
+            return;
+        };
+        let mut game = SnakeGame::from_snapshot(snapshot);
+        for (_, direction, _) in &self.pending {
+            game.change_direction(player, *direction);
+        }
+        // The snapshot is as old as the server's answers take, and so are
+        // the turns in it.
+        let ticks = (self.round_trip / TICK_INTERVAL as f64).ceil() as usize;
+        (self.onframe)(&Prediction {
+            game: &game,
+            player,
+            upcoming: vec![None; ticks.clamp(1, MAX_PREDICTION_TICKS)],
+        });
+    }
+
+    fn set_ticking(&mut self, ticking: bool) {
+        let window = window().unwrap_throw();
+        if let Some(id) = self.interval_id.take() {
+            window.clear_interval_with_handle(id);
+        }
+        if !ticking {
+            return;
+        }
+        STEP_CLOSURE.with(|step_closure| {
+            self.interval_id = Some(
+                window
+                    .set_interval_with_callback_and_timeout_and_arguments_0(
+                        step_closure.as_ref().dyn_ref::<Function>().unwrap_throw(),
+                        TICK_INTERVAL,
+                    )
+                    .unwrap_throw(),
+            );
+        });
+    }
+
+    /// Plays the next tick of a lockstep game, if everybody's input for it
+    /// has arrived.
+    fn step(&mut self) {
+        let Some(lockstep) = &mut self.lockstep else {
+            return;
+        };
+        let Some((_, messages)) = lockstep.advance() else {
+            if let Some(tick) = lockstep.desync {
+                self.set_ticking(false);
+                self.status
+                    .set_text_content(Some(&format!("out of sync after tick {}", tick)));
+                alert("The game went out of sync with the other players");
+                return;
+            }
+            let waiting_for = lockstep
+                .waiting_for()
+                .iter()
+                .map(|player| (player + 1).to_string())
+                .collect::<Vec<_>>();
+            self.status.set_text_content(Some(&format!(
+                "waiting for player {}",
+                waiting_for.join(", ")
+            )));
+            return;
+        };
+        let finished = lockstep.game.finished;
+        let outcome = lockstep.game.outcome();
+        let player = lockstep.player;
+        self.relay(messages);
+        self.status.set_text_content(Some("playing"));
+        self.render();
+        if finished {
+            self.set_ticking(false);
+            self.lockstep = None;
+            self.player = None;
+            self.send(&ClientMessage::Finished);
+            alert(&outcome_message(outcome, Some(player)));
+        }
+    }
+
+    fn receive(&mut self, message: ServerMessage) {
+        match message {
+            ServerMessage::Lobby {
+                code,
+                lockstep,
+                players,
+                you,
+            } => {
+                self.status.set_text_content(Some(&format!(
+                    "room {} · {}/{} players · {}",
+                    code,
+                    players,
+                    MAX_PLAYERS,
+                    if you == 0 {
+                        "you're the host"
+                    } else {
+                        "waiting for the host"
+                    }
+                )));
+                self.start_button.set_hidden(you != 0);
+                self.delay_button.set_hidden(!lockstep);
+            }
+            ServerMessage::Snapshot {
+                you,
+                acked,
+                snapshot,
+            } => {
+                if !snapshot.is_valid() || you >= snapshot.snakes.len() {
+                    alert("Invalid game from the server");
+                    return;
+                }
+                self.player = Some(you);
+                self.snapshot = Some(snapshot);
+                self.resyncing = false;
+                self.start_button.set_hidden(true);
+                self.confirm(acked);
+                self.render();
+            }
+            ServerMessage::Delta { acked, delta } => {
+                let Some(snapshot) = &mut self.snapshot else {
+                    return;
+                };
+                if !snapshot.apply(&delta) {
+                    if !self.resyncing {
+                        self.resyncing = true;
+                        self.send(&ClientMessage::Resync);
+                    }
+                    return;
+                }
+                self.confirm(acked);
+                self.render();
+            }
+            ServerMessage::GameOver { outcome, snapshot } => {
+                if snapshot.is_valid() && snapshot.snakes.len() > self.player.unwrap_or(0) {
+                    self.snapshot = Some(snapshot);
+                }
+                self.pending.clear();
+                self.render();
+                let message = outcome_message(outcome, self.player);
+                self.player = None;
+                alert(&message);
+            }
+            ServerMessage::Error { message } => alert(&message),
+            ServerMessage::Started { you, players, seed } => {
+                if you >= players || players > MAX_PLAYERS {
+                    alert("Invalid game from the server");
+                    return;
+                }
+                let (lockstep, messages) =
+                    Lockstep::new(seed, players, you, INPUT_DELAYS[self.input_delay]);
+                self.lockstep = Some(lockstep);
+                self.player = Some(you);
+                self.start_button.set_hidden(true);
+                self.delay_button.set_hidden(true);
+                self.relay(messages);
+                self.render();
+                self.set_ticking(true);
+            }
+            ServerMessage::Relay { from, message } => {
+                if let Some(lockstep) = &mut self.lockstep {
+                    lockstep.receive(from, message);
+                }
+            }
+            ServerMessage::Left { player } => {
+                if let Some(lockstep) = &mut self.lockstep {
+                    lockstep.leave(player);
+                }
+            }
+        }
+    }
+}
+
+fn with_online(f: impl FnOnce(&mut Online)) {
+    ONLINE.with(|online| {
+        if let Some(online) = &mut *online.borrow_mut() {
+            f(online);
+        }
+    });
+}
+
+pub fn is_online() -> bool {
+    ONLINE.with(|online| online.borrow().is_some())
+}
+
+/// Asks for a server and a room code, then joins that room, or opens a new
+/// one if no code is given. A new room runs its games in `lockstep` on every
+/// player's browser rather than on the server. `onframe` is called with every
+/// new state of the game.
+pub fn join_online(lockstep: bool, onframe: impl FnMut(&Prediction) + 'static) {
+    let window = window().unwrap_throw();
+    let document = window.document().unwrap_throw();
+    let host = window
+        .location()
+        .hostname()
+        .ok()
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| "localhost".to_string());
+    let Some(url) = window
+        .prompt_with_message_and_default("Server", &format!("ws://{}:{}", host, DEFAULT_PORT))
+        .unwrap_throw()
+    else {
+        return;
+    };
+    let Some(code) = window
+        .prompt_with_message("Room code (leave empty to open a new room)")
+        .unwrap_throw()
+    else {
+        return;
+    };
+    let Ok(socket) = WebSocket::new(&url) else {
+        alert(&format!("Invalid server address {}", url));
+        return;
+    };
+    let hello = match code.trim() {
+        "" => ClientMessage::Create { lockstep },
+        code => ClientMessage::Join {
+            code: code.to_uppercase(),
+        },
+    };
+
+    let lobby = document
+        .create_element("div")
+        .unwrap_throw()
+        .dyn_into::<HtmlElement>()
+        .unwrap_throw();
+    lobby.set_id("online-lobby");
+    style!(lobby {
+        "position": "absolute";
+        "bottom": "16px";
+        "left": "50vw";
+        "transform": "translateX(-50%)";
+        "display": "flex";
+        "gap": "4px";
+        "align-items": "center";
+        "font-family": "Roboto, sans serif";
+    });
+    let status = document
+        .create_element("span")
+        .unwrap_throw()
+        .dyn_into::<HtmlElement>()
+        .unwrap_throw();
+    status.set_text_content(Some("connecting…"));
+    let start_button = document
+        .create_element("button")
+        .unwrap_throw()
+        .dyn_into::<HtmlButtonElement>()
+        .unwrap_throw();
+    start_button.set_text_content(Some("start"));
+    start_button.set_hidden(true);
+    onclick!(start_button -> || with_online(|online| online.send(&ClientMessage::Start)));
+    let delay_button = document
+        .create_element("button")
+        .unwrap_throw()
+        .dyn_into::<HtmlButtonElement>()
+        .unwrap_throw();
+    delay_button.set_text_content(Some(&format!("input delay: {}", INPUT_DELAYS[1])));
+    delay_button.set_hidden(true);
+    onclick!(delay_button -> || with_online(|online| {
+        online.input_delay = (online.input_delay + 1) % INPUT_DELAYS.len();
+        online.delay_button.set_text_content(Some(&format!(
+            "input delay: {}",
+            INPUT_DELAYS[online.input_delay]
+        )));
+    }));
+    lobby.append_child(&status).unwrap_throw();
+    lobby.append_child(&start_button).unwrap_throw();
+    lobby.append_child(&delay_button).unwrap_throw();
+    document
+        .body()
+        .unwrap_throw()
+        .append_child(&lobby)
+        .unwrap_throw();
+
+    let onopen = Closure::wrap(Box::new(move || {
+        with_online(|online| online.send(&hello));
+    }) as Box<dyn FnMut()>);
+    let onmessage = Closure::wrap(Box::new(|event: MessageEvent| {
+        let Some(text) = event.data().as_string() else {
+            return;
+        };
+        match serde_json::from_str::<ServerMessage>(&text) {
+            Ok(message) => with_online(|online| online.receive(message)),
+            Err(e) => alert(&format!("Invalid message from server: {}", e)),
+        }
+    }) as Box<dyn FnMut(MessageEvent)>);
+    let onclose = Closure::wrap(Box::new(|| {
+        with_online(|online| {
+            online.player = None;
+            online.start_button.set_hidden(true);
+            online
+                .status
+                .set_text_content(Some("disconnected from the server"));
+        });
+    }) as Box<dyn FnMut()>);
+    socket.set_onopen(onopen.as_ref().dyn_ref());
+    socket.set_onmessage(onmessage.as_ref().dyn_ref());
+    socket.set_onclose(onclose.as_ref().dyn_ref());
+
+    ONLINE.with(|online| {
+        *online.borrow_mut() = Some(Online {
+            socket,
+            player: None,
+            snapshot: None,
+            resyncing: false,
+            pending: vec![],
+            round_trip: TICK_INTERVAL as f64,
+            next_seq: 0,
+            lockstep: None,
+            input_delay: 1,
+            interval_id: None,
+            lobby,
+            status,
+            start_button,
+            delay_button,
+            onframe: Box::new(onframe),
+            _onopen: onopen,
+            _onmessage: onmessage,
+            _onclose: onclose,
+        })
+    });
+}
+
+/// Sends a direction change for our snake, and shows it right away rather
+/// than waiting for the server to confirm it.
+pub fn send_direction(direction: Direction) {
+    with_online(|online| {
+        if online.player.is_none() {
+            return;
+        }
+        if let Some(lockstep) = &mut online.lockstep {
+            lockstep.turn(direction);
+            online.render();
+            return;
+        }
+        online.next_seq += 1;
+        online
+            .pending
+            .push((online.next_seq, direction, js_sys::Date::now()));
+        online.send(&ClientMessage::Input {
+            seq: online.next_seq,
+            direction,
+        });
+        online.render();
+    });
+}
+
+pub fn leave_online() {
+    let Some(mut online) = ONLINE.with(|online| online.borrow_mut().take()) else {
+        return;
+    };
+    online.set_ticking(false);
+    online.socket.set_onopen(None);
+    online.socket.set_onmessage(None);
+    online.socket.set_onclose(None);
+    let _ = online.socket.close();
+    online.lobby.remove();
+}
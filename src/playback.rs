@@ -0,0 +1,167 @@
+use crate::helper::{onclick, style};
+use crate::replay::{Replay, ReplayPlayer};
+use crate::snake::SnakeGame;
+use crate::TICK_INTERVAL;
+use js_sys::Function;
+use std::cell::RefCell;
+use wasm_bindgen::prelude::*;
+use web_sys::{window, HtmlButtonElement, HtmlElement};
+
+const SPEEDS: [f64; 4] = [0.5, 1.0, 2.0, 4.0];
+
+struct Playback {
+    player: ReplayPlayer,
+    playing: bool,
+    speed: usize,
+    interval_id: Option<i32>,
+    controls: HtmlElement,
+    play_button: HtmlButtonElement,
+    speed_button: HtmlButtonElement,
+    onframe: Box<dyn FnMut(&SnakeGame)>,
+    onexit: Box<dyn FnOnce()>,
+}
+
+thread_local! {
+    static PLAYBACK: RefCell<Option<Playback>> = const { RefCell::new(None) };
+    static STEP_CLOSURE: Closure<dyn FnMut()> = Closure::wrap(Box::new(step) as Box<dyn FnMut()>);
+}
+
+impl Playback {
+    fn set_playing(&mut self, playing: bool) {
+        self.playing = playing && !self.player.finished();
+        self.play_button
+            .set_text_content(Some(if self.playing { "pause" } else { "play" }));
+        self.reschedule();
+    }
+
+    fn reschedule(&mut self) {
+        let window = window().unwrap_throw();
+        if let Some(id) = self.interval_id.take() {
+            window.clear_interval_with_handle(id);
+        }
+        if !self.playing {
+            return;
+        }
+        STEP_CLOSURE.with(|step_closure| {
+            self.interval_id = Some(
+                window
+                    .set_interval_with_callback_and_timeout_and_arguments_0(
+                        step_closure.as_ref().dyn_ref::<Function>().unwrap_throw(),
+                        (TICK_INTERVAL as f64 / SPEEDS[self.speed]) as i32,
+                    )
+                    .unwrap_throw(),
+            );
+        });
+    }
+
+    fn step(&mut self) {
+        self.player.step();
+        (self.onframe)(&self.player.game);
+        if self.player.finished() {
+            self.set_playing(false);
+        }
+    }
+}
+
+fn step() {
+    PLAYBACK.with(|playback| {
+        if let Some(playback) = &mut *playback.borrow_mut() {
+            playback.step();
+        }
+    });
+}
+
+fn with_playback(f: impl FnOnce(&mut Playback)) {
+    PLAYBACK.with(|playback| {
+        if let Some(playback) = &mut *playback.borrow_mut() {
+            f(playback);
+        }
+    });
+}
+
+pub fn is_playing_back() -> bool {
+    PLAYBACK.with(|playback| playback.borrow().is_some())
+}
+
+/// Replaces the live game with a playback of `replay`. `onframe` is called
+/// with every played back state and `onexit` once the player leaves.
+pub fn start_playback(
+    replay: Replay,
+    onframe: impl FnMut(&SnakeGame) + 'static,
+    onexit: impl FnOnce() + 'static,
+) {
+    let window = window().unwrap_throw();
+    let document = window.document().unwrap_throw();
+    let controls = document
+        .create_element("div")
+        .unwrap_throw()
+        .dyn_into::<HtmlElement>()
+        .unwrap_throw();
+    controls.set_id("playback-controls");
+    style!(controls {
+        "position": "absolute";
+        "bottom": "16px";
+        "left": "50vw";
+        "transform": "translateX(-50%)";
+        "display": "flex";
+        "gap": "4px";
+    });
+    let button = |label: &str| {
+        let button = document
+            .create_element("button")
+            .unwrap_throw()
+            .dyn_into::<HtmlButtonElement>()
+            .unwrap_throw();
+        button.set_text_content(Some(label));
+        controls.append_child(&button).unwrap_throw();
+        button
+    };
+    let play_button = button("pause");
+    let step_button = button("step");
+    let speed_button = button("speed: 1x");
+    let exit_button = button("exit replay");
+
+    onclick!(play_button -> || with_playback(|playback| playback.set_playing(!playback.playing)));
+    onclick!(step_button -> || with_playback(|playback| {
+        playback.set_playing(false);
+        playback.step();
+    }));
+    onclick!(speed_button -> || with_playback(|playback| {
+        playback.speed = (playback.speed + 1) % SPEEDS.len();
+        playback
+            .speed_button
+            .set_text_content(Some(&format!("speed: {}x", SPEEDS[playback.speed])));
+        playback.reschedule();
+    }));
+    onclick!(exit_button -> exit_playback);
+
+    document
+        .body()
+        .unwrap_throw()
+        .append_child(&controls)
+        .unwrap_throw();
+
+    let mut playback = Playback {
+        player: ReplayPlayer::new(replay),
+        playing: false,
+        speed: 1,
+        interval_id: None,
+        controls,
+        play_button,
+        speed_button,
+        onframe: Box::new(onframe),
+        onexit: Box::new(onexit),
+    };
+    (playback.onframe)(&playback.player.game);
+    playback.set_playing(true);
+    PLAYBACK.with(|cell| *cell.borrow_mut() = Some(playback));
+}
+
+pub fn exit_playback() {
+    let Some(mut playback) = PLAYBACK.with(|playback| playback.borrow_mut().take()) else {
+        return;
+    };
+    playback.set_playing(false);
+    playback.controls.remove();
+    (playback.onexit)();
+}
@@ -1,11 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+#[cfg(target_family = "wasm")]
 use wasm_bindgen::prelude::*;
 
+#[cfg(target_family = "wasm")]
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = Math)]
     fn random() -> f64;
 }
 
-pub fn random_range(min: usize, max: usize) -> usize {
-    (random() * (max - min) as f64).floor() as usize + min
+/// Returns a fresh, non-deterministic seed for [`Rng::new`].
+#[cfg(target_family = "wasm")]
+pub fn random_seed() -> u64 {
+    // `Math.random` only carries 53 bits of entropy, call it twice to fill 64.
+    let high = (random() * u32::MAX as f64) as u64;
+    let low = (random() * u32::MAX as f64) as u64;
+    high << 32 | low
+}
+
+/// Returns a fresh, non-deterministic seed for [`Rng::new`].
+#[cfg(not(target_family = "wasm"))]
+pub fn random_seed() -> u64 {
+    rand::random()
+}
+
+/// A small seedable PRNG (SplitMix64), so that a game can be reproduced
+/// exactly from its seed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn range(&mut self, min: usize, max: usize) -> usize {
+        (self.next_u64() % (max - min) as u64) as usize + min
+    }
 }
@@ -1,4 +1,7 @@
+use crate::entity::EntityKind;
 use crate::helper::{CallbackFn, Vector};
+use crate::snake::{Direction, FoodKind};
+use crate::TICK_INTERVAL;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
@@ -10,7 +13,6 @@ pub struct Renderer {
     pub height: usize,
     pub pixel_size: usize,
     pub onclick: Option<CallbackFn<Vector>>,
-    pub score: usize,
     pub high_score: usize,
 }
 
@@ -28,7 +30,6 @@ impl Renderer {
             height,
             pixel_size,
             onclick: None,
-            score: 0,
             high_score,
         };
         if let Some(onclick) = onclick {
@@ -38,8 +39,158 @@ impl Renderer {
     }
 }
 
+/// What occupies a single cell of the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cell {
+    Empty,
+    /// A snake's head, facing the direction it moves in.
+    Head {
+        player: usize,
+        facing: Direction,
+    },
+    /// A body segment, with the directions of its neighbouring segments.
+    Body {
+        player: usize,
+        toward_head: Direction,
+        toward_tail: Direction,
+    },
+    /// The last segment, with the direction of the segment in front of it.
+    Tail {
+        player: usize,
+        toward_head: Direction,
+    },
+    Food(FoodKind),
+    /// Food that will soon rot into poison.
+    Rotting(FoodKind),
+    /// An end of the portal pair with this number.
+    Portal(usize),
+    Entity(EntityKind),
+}
+
+/// Head and body colours of each player's snake.
+const PLAYER_GLYPHS: [(char, char); 4] = [('🟨', '⬛'), ('🟦', '🟪'), ('🟧', '🟫'), ('🟩', '🟥')];
+/// Glyph of each pair of portals.
+const PORTAL_GLYPHS: [char; 4] = ['🌀', '🔵', '🟣', '🔴'];
+
+impl Cell {
+    fn glyph(self) -> char {
+        let glyphs = |player: usize| PLAYER_GLYPHS[player % PLAYER_GLYPHS.len()];
+        match self {
+            Cell::Empty => ' ',
+            Cell::Head { player, .. } => glyphs(player).0,
+            Cell::Body { player, .. } | Cell::Tail { player, .. } => glyphs(player).1,
+            Cell::Food(kind) | Cell::Rotting(kind) => kind.icon(),
+            Cell::Portal(pair) => PORTAL_GLYPHS[pair % PORTAL_GLYPHS.len()],
+            Cell::Entity(kind) => kind.icon(),
+        }
+    }
+
+    /// CSS classes of the cell, so a stylesheet can theme the board.
+    fn class_name(self) -> String {
+        let direction = |direction: Direction| format!("{:?}", direction).to_lowercase();
+        match self {
+            Cell::Empty => "cell empty".to_string(),
+            Cell::Head { player, facing } => {
+                format!("cell head player-{} facing-{}", player, direction(facing))
+            }
+            Cell::Body {
+                player,
+                toward_head,
+                toward_tail,
+            } => format!(
+                "cell body player-{} from-{} to-{}",
+                player,
+                direction(toward_tail),
+                direction(toward_head)
+            ),
+            Cell::Tail {
+                player,
+                toward_head,
+            } => format!("cell tail player-{} to-{}", player, direction(toward_head)),
+            Cell::Food(kind) => format!("cell food {:?}", kind).to_lowercase(),
+            Cell::Rotting(kind) => format!("cell food rotting {:?}", kind).to_lowercase(),
+            Cell::Portal(pair) => format!("cell portal pair-{}", pair),
+            Cell::Entity(kind) => format!("cell entity {:?}", kind).to_lowercase(),
+        }
+    }
+
+    /// Clockwise rotation of the cell's sprite, relative to facing up.
+    fn rotation(self) -> u32 {
+        let degrees = |direction| match direction {
+            Direction::Up => 0,
+            Direction::Right => 90,
+            Direction::Down => 180,
+            Direction::Left => 270,
+        };
+        match self {
+            Cell::Head { facing, .. } => degrees(facing),
+            Cell::Tail { toward_head, .. } => degrees(toward_head),
+            _ => 0,
+        }
+    }
+
+    /// CSS filter the cell's sprite is drawn with.
+    fn filter(self) -> &'static str {
+        match self {
+            Cell::Rotting(_) => "sepia(1) hue-rotate(45deg)",
+            _ => "none",
+        }
+    }
+}
+
+/// A cell drawn over the board, like the ghost of a previous run.
+pub struct Overlay {
+    pub pos: Vector,
+    pub cell: Cell,
+    pub opacity: f64,
+}
+
+/// Values shown next to the board.
+#[derive(Debug, Default)]
+pub struct Hud {
+    /// Score of every player, in player order.
+    pub scores: Vec<usize>,
+    /// Ticks left on the clock of a timed game.
+    pub time_left: Option<usize>,
+}
+
+/// A full description of what to draw.
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<Cell>,
+    pub overlays: Vec<Overlay>,
+    pub hud: Hud,
+}
+
+impl Frame {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![Cell::Empty; width * height],
+            overlays: vec![],
+            hud: Hud::default(),
+        }
+    }
+
+    fn index(&self, pos: &Vector) -> Option<usize> {
+        pos.index_in(self.width, self.height)
+    }
+
+    pub fn cell(&self, pos: &Vector) -> Cell {
+        self.index(pos).map_or(Cell::Empty, |i| self.cells[i])
+    }
+
+    pub fn set(&mut self, pos: &Vector, cell: Cell) {
+        if let Some(i) = self.index(pos) {
+            self.cells[i] = cell;
+        }
+    }
+}
+
 pub trait Renderable {
-    fn renderer_data(&self) -> HashMap<Vector, char>;
+    fn frame(&self) -> Frame;
 }
 
 impl Default for Renderer {
@@ -49,7 +200,6 @@ impl Default for Renderer {
             height: 10,
             pixel_size: 30,
             onclick: None,
-            score: 0,
             high_score: 0,
         }
     }
@@ -63,9 +213,33 @@ macro_rules! style {
     }};
 }
 
+fn sprite(cell: Cell, opacity: f64) -> HtmlElement {
+    let window = window().unwrap_throw();
+    let document = window.document().unwrap_throw();
+    let span = document
+        .create_element("span")
+        .unwrap_throw()
+        .dyn_into::<HtmlElement>()
+        .unwrap_throw();
+    span.set_text_content(Some(&format!("{}", cell.glyph())));
+    style!(span {
+        "position": "absolute";
+        "top": "0";
+        "left": "0";
+        "opacity": &opacity.to_string();
+        "transform": &format!("rotate({}deg)", cell.rotation());
+        "filter": cell.filter();
+    });
+    span
+}
+
 impl Renderer {
     pub fn render(&mut self, object: &impl Renderable) {
-        let data = object.renderer_data();
+        let frame = object.frame();
+        let mut overlays: HashMap<Vector, Vec<&Overlay>> = HashMap::new();
+        for overlay in &frame.overlays {
+            overlays.entry(overlay.pos).or_default().push(overlay);
+        }
         let window = window().unwrap_throw();
         let document = window.document().unwrap_throw();
         let (display, score_counter) = match document.get_element_by_id("display") {
@@ -119,24 +293,46 @@ impl Renderer {
             }
         };
         display.set_inner_html("");
-        score_counter.set_inner_html(&format!(
-            "score: {}<br>high score: {}",
-            self.score, self.high_score
-        ));
+        let mut hud = match frame.hud.scores.as_slice() {
+            [score] => format!("score: {}<br>high score: {}", score, self.high_score),
+            scores => scores
+                .iter()
+                .enumerate()
+                .map(|(player, score)| {
+                    format!(
+                        "{} player {}: {}",
+                        PLAYER_GLYPHS[player % PLAYER_GLYPHS.len()].0,
+                        player + 1,
+                        score
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("<br>"),
+        };
+        if let Some(ticks) = frame.hud.time_left {
+            let seconds = (ticks * TICK_INTERVAL as usize).div_ceil(1000);
+            hud.push_str(&format!("<br>time: {}:{:02}", seconds / 60, seconds % 60));
+        }
+        score_counter.set_inner_html(&hud);
 
         for y in 0..self.height {
             for x in 0..self.width {
                 let coords = Vector(x as isize, y as isize);
-                let value = data.get(&coords).unwrap_throw();
 
                 let anchor = document
                     .create_element("a")
                     .unwrap_throw()
                     .dyn_into::<HtmlElement>()
                     .unwrap_throw();
-                anchor.set_text_content(Some(&format!("{}", value)));
-                if self.onclick.is_some() {
-                    let onclick = self.onclick.as_ref().unwrap();
+                let cell = frame.cell(&coords);
+                anchor.set_class_name(&cell.class_name());
+                anchor.append_child(&sprite(cell, 1.0)).unwrap_throw();
+                for overlay in overlays.get(&coords).into_iter().flatten() {
+                    anchor
+                        .append_child(&sprite(overlay.cell, overlay.opacity))
+                        .unwrap_throw();
+                }
+                if let Some(onclick) = &self.onclick {
                     let onclick_closure = Closure::wrap(Box::new({
                         let onclick = onclick.clone();
                         move || {
@@ -146,6 +342,7 @@ impl Renderer {
                     anchor.set_onclick(onclick_closure.as_ref().dyn_ref())
                 }
                 style!(anchor {
+                    "position": "relative";
                     "width": &format!("{}px", self.pixel_size);
                     "height": &format!("{}px", self.pixel_size)
                 });
@@ -0,0 +1,161 @@
+use crate::helper::Vector;
+use crate::snake::{Direction, GameEvent, Rules, SnakeGame, MAX_CELLS};
+use serde::{Deserialize, Serialize};
+
+/// A compact recording of a game: its seed and board plus every accepted
+/// direction change, which is enough to reproduce it exactly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub width: usize,
+    pub height: usize,
+    pub food_count: usize,
+    #[serde(default)]
+    pub rules: Rules,
+    #[serde(default)]
+    pub portals: Vec<(Vector, Vector)>,
+    pub score: usize,
+    pub inputs: Vec<(usize, Direction)>,
+    #[serde(default)]
+    pub boosts: Vec<(usize, bool)>,
+}
+
+/// Ticks a replay from outside may take to end before it is taken for one
+/// that never does.
+const MAX_VERIFIED_TICKS: usize = 100_000;
+
+impl Replay {
+    /// The replay with its score worked out by playing it through, or `None`
+    /// if it can't be played: its board is empty or too big, or the game
+    /// doesn't end. For replays that weren't recorded here.
+    pub fn verified(mut self) -> Option<Replay> {
+        let fits = self
+            .width
+            .checked_mul(self.height)
+            .is_some_and(|cells| cells > 0 && cells <= MAX_CELLS);
+        if !fits {
+            return None;
+        }
+        let mut player = ReplayPlayer::new(self.clone());
+        while !player.finished() {
+            if player.game.ticks >= MAX_VERIFIED_TICKS {
+                return None;
+            }
+            player.step();
+        }
+        self.score = player.game.score(0);
+        Some(self)
+    }
+}
+
+pub struct ReplayPlayer {
+    pub game: SnakeGame,
+    replay: Replay,
+    cursor: usize,
+    boost_cursor: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            game: SnakeGame::with_seed(replay.width, replay.height, replay.food_count, replay.seed)
+                .with_rules(replay.rules)
+                .with_portals(replay.portals.clone()),
+            replay,
+            cursor: 0,
+            boost_cursor: 0,
+        }
+    }
+
+    /// Feeds the inputs recorded for the current tick and advances the game.
+    pub fn step(&mut self) -> Vec<GameEvent> {
+        while let Some(&(tick, direction)) = self.replay.inputs.get(self.cursor) {
+            if tick != self.game.ticks {
+                break;
+            }
+            self.game.change_direction(0, direction);
+            self.cursor += 1;
+        }
+        while let Some(&(tick, boosting)) = self.replay.boosts.get(self.boost_cursor) {
+            if tick != self.game.ticks {
+                break;
+            }
+            self.game.set_boost(0, boosting);
+            self.boost_cursor += 1;
+        }
+        self.game.tick()
+    }
+
+    pub fn finished(&self) -> bool {
+        self.game.finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::StrategyKind;
+    use crate::snake::SelfCollision;
+
+    /// Plays a game with a computer player that also boosts now and then,
+    /// returning it along with its state hash after every tick.
+    fn play(rules: Rules, seed: u64) -> (SnakeGame, Vec<u64>) {
+        let mut game = SnakeGame::with_seed(16, 12, 5, seed).with_rules(rules);
+        let mut strategy = StrategyKind::Wanderer.build_with_seed(seed);
+        let mut hashes = vec![];
+        while !game.finished && game.ticks < 2000 {
+            game.change_direction(0, strategy.next_direction(&game.view(0)));
+            if game.ticks.is_multiple_of(15) {
+                game.set_boost(0, game.ticks.is_multiple_of(30));
+            }
+            game.tick();
+            hashes.push(game.state_hash());
+        }
+        (game, hashes)
+    }
+
+    #[test]
+    fn replay_reproduces_every_state() {
+        let rule_sets = [
+            Rules::default(),
+            Rules::ZEN,
+            Rules {
+                self_collision: SelfCollision::Cut,
+                portals: 2,
+                hazards: 3,
+                moving_food: true,
+                rot_ticks: Some(20),
+                ..Rules::default()
+            },
+        ];
+        for rules in rule_sets {
+            for seed in 0..5 {
+                let (game, hashes) = play(rules, seed);
+                let mut player = ReplayPlayer::new(game.replay());
+                let replayed = hashes
+                    .iter()
+                    .map(|_| {
+                        player.step();
+                        player.game.state_hash()
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(replayed, hashes, "rules {:?}, seed {}", rules, seed);
+                assert_eq!(player.finished(), game.finished);
+            }
+        }
+    }
+
+    #[test]
+    fn verified_replays_score_what_they_play() {
+        let (game, _) = play(Rules::default(), 3);
+        assert!(game.finished);
+        let mut replay = game.replay();
+        replay.score = 9999;
+        let verified = replay.clone().verified().unwrap();
+        assert_eq!(verified.score, game.score(0));
+        replay.width = usize::MAX;
+        assert!(replay.clone().verified().is_none());
+        replay.width = 0;
+        assert!(replay.verified().is_none());
+    }
+}
@@ -0,0 +1,236 @@
+use crate::{
+    ai::Difficulty,
+    audio::AudioSettings,
+    daily::DailyRecord,
+    ghost::Ghost,
+    replay::Replay,
+    snake::SnakeGame,
+    stats::{Stats, VersusRecord},
+};
+use js_sys::Array;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+use web_sys::{
+    window, Blob, BlobPropertyBag, FileReader, HtmlAnchorElement, HtmlInputElement, Storage, Url,
+};
+
+const STORAGE_KEY: &str = "snake-game-data";
+/// Key of the game in progress, kept apart from the save data so it is
+/// neither exported nor merged.
+const GAME_STATE_KEY: &str = "snake-game-state";
+/// Key of the name of the mode the game in progress is played in.
+const GAME_STATE_MODE_KEY: &str = "snake-game-state-mode";
+const EXPORT_FILE_NAME: &str = "snake-game-save.json";
+/// Milliseconds the download link of an export is kept for. Some browsers
+/// only start the download after the click has returned.
+const EXPORT_URL_LIFETIME: i32 = 10_000;
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct GameData {
+    pub high_score: usize,
+    #[serde(default)]
+    pub stats: Stats,
+    /// Replay of the best game played so far.
+    #[serde(default)]
+    pub best_replay: Option<Replay>,
+    /// Path of the best game played so far, raced against as a ghost.
+    #[serde(default)]
+    pub best_ghost: Option<Ghost>,
+    #[serde(default)]
+    pub versus_ai: HashMap<Difficulty, VersusRecord>,
+    #[serde(default)]
+    pub audio: AudioSettings,
+    /// Best time attack scores, keyed by the length of the game in seconds.
+    #[serde(default)]
+    pub time_attack: HashMap<usize, usize>,
+    /// Best scores of the solo modes with rules of their own, like portals,
+    /// keyed by the mode's name.
+    #[serde(default)]
+    pub mode_high_scores: HashMap<String, usize>,
+    #[serde(default)]
+    pub daily: DailyRecord,
+}
+
+impl GameData {
+    /// Merges an imported save into this one, keeping the best of both
+    /// instead of overwriting.
+    pub fn merge(&mut self, other: GameData) {
+        self.high_score = self.high_score.max(other.high_score);
+        // Lifetime stats can't be added together without double counting a
+        // save that was exported from this browser, so keep the longer history.
+        if other.stats.games_played > self.stats.games_played {
+            self.stats = other.stats;
+        }
+        // An imported file could hold anything, so the replay's score is
+        // worked out again and a ghost that can't be drawn is left out.
+        if let Some(replay) = other.best_replay.and_then(Replay::verified) {
+            self.record_replay(replay);
+        }
+        if let Some(ghost) = other.best_ghost.filter(Ghost::is_valid) {
+            self.record_ghost(ghost);
+        }
+        for (difficulty, record) in other.versus_ai {
+            let current = self.versus_ai.entry(difficulty).or_default();
+            if record.games() > current.games() {
+                *current = record;
+            }
+        }
+        for (seconds, score) in other.time_attack {
+            let best = self.time_attack.entry(seconds).or_default();
+            *best = (*best).max(score);
+        }
+        for (mode, score) in other.mode_high_scores {
+            let best = self.mode_high_scores.entry(mode).or_default();
+            *best = (*best).max(score);
+        }
+        self.daily.merge(other.daily);
+        // Sound settings belong to this browser, so they are left as they are.
+    }
+
+    /// Keeps `replay` if it beats the best one stored so far.
+    pub fn record_replay(&mut self, replay: Replay) {
+        if self
+            .best_replay
+            .as_ref()
+            .is_none_or(|best| replay.score > best.score)
+        {
+            self.best_replay = Some(replay);
+        }
+    }
+
+    /// Keeps `ghost` if it beats the best one stored so far.
+    pub fn record_ghost(&mut self, ghost: Ghost) {
+        if self
+            .best_ghost
+            .as_ref()
+            .is_none_or(|best| ghost.score() > best.score())
+        {
+            self.best_ghost = Some(ghost);
+        }
+    }
+}
+
+fn storage() -> Result<Storage, JsValue> {
+    let window = window().ok_or("Window not found")?;
+    Ok(window
+        .local_storage()?
+        .ok_or("Local storage is not supported")?)
+}
+
+pub fn save_game_data(data: &GameData) -> Result<(), JsValue> {
+    let storage = storage()?;
+    let string = serde_json::to_string(data).map_err(|e| JsValue::from(e.to_string()))?;
+    storage.set_item(STORAGE_KEY, &string)?;
+    Ok(())
+}
+
+pub fn load_game_data() -> Result<GameData, JsValue> {
+    let storage = storage()?;
+    if let Some(string) = storage.get_item(STORAGE_KEY)? {
+        serde_json::from_str(&string).map_err(|e| JsValue::from(e.to_string()))
+    } else {
+        Err("Game data not found".into())
+    }
+}
+
+/// Stores the game in progress and the name of its mode, to pick it back up
+/// after a reload.
+pub fn save_game_state(game: &SnakeGame, mode: &str) -> Result<(), JsValue> {
+    let string = serde_json::to_string(game).map_err(|e| JsValue::from(e.to_string()))?;
+    let storage = storage()?;
+    storage.set_item(GAME_STATE_KEY, &string)?;
+    storage.set_item(GAME_STATE_MODE_KEY, mode)
+}
+
+pub fn clear_game_state() -> Result<(), JsValue> {
+    let storage = storage()?;
+    storage.remove_item(GAME_STATE_KEY)?;
+    storage.remove_item(GAME_STATE_MODE_KEY)
+}
+
+/// The game in progress, and the name of its mode if it was saved with one.
+pub fn load_game_state() -> Result<(SnakeGame, Option<String>), JsValue> {
+    let storage = storage()?;
+    match storage.get_item(GAME_STATE_KEY)? {
+        Some(string) => {
+            let game = SnakeGame::from_json(&string).map_err(JsValue::from)?;
+            Ok((game, storage.get_item(GAME_STATE_MODE_KEY)?))
+        }
+        None => Err("No game in progress".into()),
+    }
+}
+
+/// Downloads the save data as a JSON file.
+pub fn export_game_data(data: &GameData) -> Result<(), JsValue> {
+    let window = window().ok_or("Window not found")?;
+    let document = window.document().ok_or("Document not found")?;
+    let string = serde_json::to_string_pretty(data).map_err(|e| JsValue::from(e.to_string()))?;
+    let options = BlobPropertyBag::new();
+    options.set_type("application/json");
+    let blob =
+        Blob::new_with_str_sequence_and_options(&Array::of1(&JsValue::from(string)), &options)?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+    let anchor = document
+        .create_element("a")?
+        .dyn_into::<HtmlAnchorElement>()?;
+    anchor.set_href(&url);
+    anchor.set_download(EXPORT_FILE_NAME);
+    // Firefox ignores clicks on links that aren't in the page.
+    let body = document.body().ok_or("Body not found")?;
+    body.append_child(&anchor)?;
+    anchor.click();
+    anchor.remove();
+    let revoke = Closure::once_into_js(move || {
+        let _ = Url::revoke_object_url(&url);
+    });
+    window.set_timeout_with_callback_and_timeout_and_arguments_0(
+        revoke.unchecked_ref(),
+        EXPORT_URL_LIFETIME,
+    )?;
+    Ok(())
+}
+
+/// Lets the player pick a previously exported save file and hands the parsed
+/// result to `onload`.
+pub fn import_game_data(onload: impl FnOnce(Result<GameData, String>) + 'static) {
+    let window = window().unwrap_throw();
+    let document = window.document().unwrap_throw();
+    let input = document
+        .create_element("input")
+        .unwrap_throw()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap_throw();
+    input.set_type("file");
+    input.set_accept(".json,application/json");
+
+    let onchange = Closure::once(Box::new({
+        let input = input.clone();
+        move || {
+            let Some(file) = input.files().and_then(|files| files.get(0)) else {
+                return;
+            };
+            let reader = FileReader::new().unwrap_throw();
+            let onloadend = Closure::once(Box::new({
+                let reader = reader.clone();
+                move || {
+                    let result = reader
+                        .result()
+                        .ok()
+                        .and_then(|result| result.as_string())
+                        .ok_or_else(|| "Could not read file".to_string())
+                        .and_then(|string| {
+                            serde_json::from_str::<GameData>(&string).map_err(|e| e.to_string())
+                        });
+                    onload(result);
+                }
+            }) as Box<dyn FnOnce()>);
+            reader.set_onloadend(onloadend.as_ref().dyn_ref());
+            onloadend.forget();
+            reader.read_as_text(&file).unwrap_throw();
+        }
+    }) as Box<dyn FnOnce()>);
+    input.set_onchange(onchange.as_ref().dyn_ref());
+    onchange.forget();
+    input.click();
+}
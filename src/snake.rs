@@ -1,51 +1,25 @@
-use crate::{helper::Vector, random::random_range, renderer::Renderable};
+use crate::{
+    ai::GameView,
+    entity::{distance, Entity, EntityKind},
+    ghost::Ghost,
+    helper::Vector,
+    net::{SnakeState, Snapshot},
+    random::{random_seed, Rng},
+    renderer::{Cell, Frame, Renderable},
+    replay::Replay,
+};
 use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
 use std::collections::{HashMap, VecDeque};
 use wasm_bindgen::prelude::*;
-use web_sys::{window, HtmlAudioElement};
 
 #[wasm_bindgen]
 extern "C" {
     pub fn alert(s: &str);
 }
 
-fn get_audio_element() -> HtmlAudioElement {
-    let window = window().unwrap_throw();
-    let document = window.document().unwrap_throw();
-    match document.get_element_by_id("audio-player") {
-        Some(audio) => audio.dyn_into().unwrap_throw(),
-        None => {
-            let audio = HtmlAudioElement::new_with_src("./assets/eating.mp3").unwrap_throw();
-            document.body().unwrap_throw().append_child(&audio).unwrap();
-            audio
-        }
-    }
-}
-
-pub fn save_game_data(data: GameData) -> Result<(), JsValue> {
-    let window = window().ok_or("Window not found")?;
-    let storage = window
-        .local_storage()?
-        .ok_or("Local storage is not supported")?;
-    let string = serde_json::to_string(&data).map_err(|e| JsValue::from(e.to_string()))?;
-    storage.set_item("snake-game-data", &string)?;
-    Ok(())
-}
-
-pub fn load_game_data() -> Result<GameData, JsValue> {
-    let window = window().ok_or("Window not found")?;
-    let storage = window
-        .local_storage()?
-        .ok_or("Local storage is not supported")?;
-    if let Some(string) = storage.get_item("snake-game-data")? {
-        serde_json::from_str(&string).map_err(|e| JsValue::from(e.to_string()))
-    } else {
-        Err("Game data not found".into())
-    }
-}
-
 #[allow(dead_code)]
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Left,
@@ -55,7 +29,14 @@ pub enum Direction {
 
 #[allow(dead_code)]
 impl Direction {
-    fn opposite(&self) -> Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Left,
+        Direction::Down,
+        Direction::Right,
+    ];
+
+    pub fn opposite(&self) -> Direction {
         match self {
             Direction::Up => Direction::Down,
             Direction::Left => Direction::Right,
@@ -64,7 +45,18 @@ impl Direction {
         }
     }
 
-    fn to_vec(self) -> Vector {
+    /// The direction from `from` to the adjacent cell `to`.
+    pub fn between(from: &Vector, to: &Vector) -> Option<Direction> {
+        match (to.0 - from.0, to.1 - from.1) {
+            (0, -1) => Some(Direction::Up),
+            (-1, 0) => Some(Direction::Left),
+            (0, 1) => Some(Direction::Down),
+            (1, 0) => Some(Direction::Right),
+            _ => None,
+        }
+    }
+
+    pub fn to_vec(self) -> Vector {
         match self {
             Direction::Up => Vector(0, -1),
             Direction::Left => Vector(-1, 0),
@@ -74,127 +66,1907 @@ impl Direction {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum FoodKind {
+    Carrot,
+    Bread,
+    Avocado,
+    Cherry,
+    /// Hops away from snakes that come near.
+    Rabbit,
+    /// Crawls around at random.
+    Bug,
+    /// Food gone bad, which shrinks the snake that eats it.
+    Poison,
+}
+
+impl FoodKind {
+    pub const ALL: [FoodKind; 7] = [
+        FoodKind::Carrot,
+        FoodKind::Bread,
+        FoodKind::Avocado,
+        FoodKind::Cherry,
+        FoodKind::Rabbit,
+        FoodKind::Bug,
+        FoodKind::Poison,
+    ];
+    /// The kinds put down in a game with moving food.
+    pub const WILD: [FoodKind; 6] = [
+        FoodKind::Carrot,
+        FoodKind::Bread,
+        FoodKind::Avocado,
+        FoodKind::Cherry,
+        FoodKind::Rabbit,
+        FoodKind::Bug,
+    ];
+    /// The kinds that stay where they are put, the only ones in a game
+    /// without moving food.
+    pub const STILL: [FoodKind; 4] = [
+        FoodKind::Carrot,
+        FoodKind::Bread,
+        FoodKind::Avocado,
+        FoodKind::Cherry,
+    ];
+
+    pub fn icon(self) -> char {
+        match self {
+            FoodKind::Carrot => '🥕',
+            FoodKind::Bread => '🍞',
+            FoodKind::Avocado => '🥑',
+            FoodKind::Cherry => '🍒',
+            FoodKind::Rabbit => '🐇',
+            FoodKind::Bug => '🐛',
+            FoodKind::Poison => '🍄',
+        }
+    }
+
+    /// Ticks between two moves, or `None` if the food doesn't move.
+    pub fn period(self) -> Option<usize> {
+        match self {
+            FoodKind::Rabbit => Some(3),
+            FoodKind::Bug => Some(2),
+            _ => None,
+        }
+    }
+
+    /// Ticks eating this adds to the clock of a timed game.
+    pub fn bonus_ticks(self) -> usize {
+        match self {
+            FoodKind::Cherry => 10,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum DeathCause {
+    Wall,
+    OwnBody,
+    /// Ran into another snake's body.
+    OtherSnake,
+    /// Two heads moved into the same cell.
+    HeadOn,
+    /// Ran into a hazard, or a hazard ran into it.
+    Hazard,
+    /// Ate poison while too short to lose that much.
+    Poison,
+}
+
+/// Something that happened during a single [`SnakeGame::tick`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GameEvent {
+    /// A queued direction change was applied.
+    Turned {
+        player: usize,
+        direction: Direction,
+    },
+    Ate {
+        player: usize,
+        kind: FoodKind,
+    },
+    Died {
+        player: usize,
+        cause: DeathCause,
+    },
+    /// The snake ran into its own body under [`SelfCollision::Cut`] and lost
+    /// everything from there to its tail, `lost` cells in all.
+    Cut {
+        player: usize,
+        lost: usize,
+    },
+    /// The snakes filled the board, so the game is over. `player` has the
+    /// longest snake.
+    FilledBoard {
+        player: usize,
+    },
+    /// The clock of a timed game ran out, which ends it.
+    TimeUp,
+    /// The snake ate a star and can eat hazards for a while.
+    PoweredUp {
+        player: usize,
+    },
+    /// The powered-up snake ate a hazard.
+    Devoured {
+        player: usize,
+        kind: EntityKind,
+    },
+    /// The snake ate poison and lost `lost` cells off its tail.
+    Poisoned {
+        player: usize,
+        lost: usize,
+    },
+    /// Boosting cost the snake the last cell of its tail.
+    Shed {
+        player: usize,
+    },
+}
+
+impl GameEvent {
+    /// The player the event happened to, if it wasn't the whole game.
+    pub fn player(&self) -> Option<usize> {
+        match self {
+            GameEvent::Turned { player, .. }
+            | GameEvent::Ate { player, .. }
+            | GameEvent::Died { player, .. }
+            | GameEvent::Cut { player, .. }
+            | GameEvent::FilledBoard { player }
+            | GameEvent::PoweredUp { player }
+            | GameEvent::Devoured { player, .. }
+            | GameEvent::Poisoned { player, .. }
+            | GameEvent::Shed { player } => Some(*player),
+            GameEvent::TimeUp => None,
+        }
+    }
+}
+
+/// What is in a cell of the board.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+enum Occupant {
+    #[default]
+    Empty,
+    Food,
+    Portal,
+    Entity,
+    /// A part of the snake of the player.
+    Snake(usize),
+}
+
+/// The contents of every cell, kept in step with the snakes and foods so a
+/// cell can be looked up without searching through them.
+#[derive(Debug, Default, Clone)]
+struct Grid {
+    width: usize,
+    height: usize,
+    cells: Vec<Occupant>,
+}
+
+impl Grid {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![Occupant::Empty; width * height],
+        }
+    }
+
+    fn index(&self, pos: &Vector) -> Option<usize> {
+        pos.index_in(self.width, self.height)
+    }
+
+    /// What is at `pos`, or `None` if it is off the board.
+    fn get(&self, pos: &Vector) -> Option<Occupant> {
+        self.index(pos).map(|i| self.cells[i])
+    }
+
+    fn set(&mut self, pos: &Vector, occupant: Occupant) {
+        if let Some(i) = self.index(pos) {
+            self.cells[i] = occupant;
+        }
+    }
+}
+
+/// How a game with more than one player ended.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Outcome {
+    Won(usize),
+    Draw,
+}
+
+/// What happens to a snake that runs into its own body.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum SelfCollision {
+    #[default]
+    Die,
+    /// The snake bites itself off where it ran into itself and carries on.
+    Cut,
+}
+
+/// The rules a game is played by, chosen when it starts.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rules {
+    pub self_collision: SelfCollision,
+    /// Whether a snake leaving the board comes back in on the other side
+    /// rather than hitting the wall.
+    pub wrap: bool,
+    /// Ticks the game lasts, not counting bonus time, if it is timed.
+    pub time_limit: Option<usize>,
+    /// Pairs of portals placed at random when the game starts.
+    pub portals: usize,
+    /// Hazards kept on the board, along with a star to eat them with.
+    pub hazards: usize,
+    /// Whether some of the food is rabbits and bugs, which move.
+    pub moving_food: bool,
+    /// Ticks food lasts before rotting into poison, if it rots. Poison rots
+    /// away after as long again.
+    pub rot_ticks: Option<usize>,
+    /// Whether the game is over as soon as player one dies, rather than
+    /// once a single snake is left. For games where everyone else is a
+    /// computer player.
+    pub ends_with_player_one: bool,
+}
+
+impl Rules {
+    /// An endless game with nothing to run into but other snakes.
+    pub const ZEN: Rules = Rules {
+        self_collision: SelfCollision::Cut,
+        wrap: true,
+        time_limit: None,
+        portals: 0,
+        hazards: 0,
+        moving_food: false,
+        rot_ticks: None,
+        ends_with_player_one: false,
+    };
+}
+
+/// Direction changes a player can queue up ahead of the snake.
+pub const MAX_QUEUED_DIRECTIONS: usize = 3;
+/// Mixed into the seed of the generator placing random portals, which is
+/// kept apart from the food's.
+const PORTAL_SALT: u64 = 0x9e3d_7a11_f00d_ca7e;
+/// Ticks a star lets a snake eat hazards for.
+pub const POWER_TICKS: usize = 30;
+/// Hazards don't appear closer than this to a snake's head.
+const HAZARD_DISTANCE: isize = 5;
+/// Rabbits only run from snake heads at most this far away.
+const FLEE_DISTANCE: isize = 4;
+/// Cells eating poison takes off a snake, on top of not growing.
+pub const POISON_LOSS: usize = 3;
+/// Food shows it is rotting for the last 1/`ROTTING_SHARE` of its time.
+const ROTTING_SHARE: usize = 3;
+/// A boosting snake loses a cell off its tail every this many ticks.
+pub const BOOST_COST: usize = 4;
+/// The most cells the board of a loaded game or an online snapshot can have,
+/// which keeps bad data from asking for more memory than there is.
+pub const MAX_CELLS: usize = 1 << 20;
+
+/// 64-bit FNV-1a. Unlike the standard library's hasher it gives the same
+/// result on every platform and in every build, so browsers can compare
+/// hashes with each other.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_vector(&mut self, pos: &Vector) {
+        self.write_u64(pos.0 as u64);
+        self.write_u64(pos.1 as u64);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snake {
+    pub body: VecDeque<Vector>,
+    direction: Direction,
+    /// Direction changes still to be applied, one per tick.
+    queue: VecDeque<Direction>,
+    pub alive: bool,
+    /// Number of ticks the snake has survived.
+    pub ticks: usize,
+    pub food_eaten: HashMap<FoodKind, usize>,
+    /// Number of ticks spent moving in each direction.
+    pub direction_ticks: HashMap<Direction, usize>,
+    /// Ticks left in which the snake can eat hazards.
+    #[serde(default)]
+    pub powered: usize,
+    /// Whether the player is holding boost down.
+    #[serde(default)]
+    boosting: bool,
+    /// Ticks the snake has moved boosted, for [`BOOST_COST`].
+    #[serde(default)]
+    boosted_ticks: usize,
+    /// Most cells the snake has had at once, since poison and boosting
+    /// shrink it again.
+    #[serde(default)]
+    peak_length: usize,
+}
+
+impl Snake {
+    fn new(head: Vector, direction: Direction) -> Self {
+        Self {
+            body: [head].into_iter().collect(),
+            direction,
+            queue: VecDeque::new(),
+            alive: true,
+            ticks: 0,
+            food_eaten: HashMap::new(),
+            direction_ticks: HashMap::new(),
+            powered: 0,
+            boosting: false,
+            boosted_ticks: 0,
+            peak_length: 1,
+        }
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn score(&self) -> usize {
+        self.body.len() - 1
+    }
+
+    /// The longest the snake has been. Games saved before this was kept
+    /// only know how long it is now.
+    pub fn peak_length(&self) -> usize {
+        self.peak_length.max(self.body.len())
+    }
+
+    /// Whether the snake moves boosted. A snake that is only a head has
+    /// nothing left to pay for it with.
+    pub fn is_boosting(&self) -> bool {
+        self.alive && self.boosting && self.body.len() > 1
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SnakeGame {
     width: usize,
     height: usize,
-    pub snake: VecDeque<Vector>,
+    pub snakes: Vec<Snake>,
     foods: Vec<Vector>,
-    food_icons: Vec<char>,
-    direction: Direction,
-    changed_direction: Direction,
+    food_kinds: Vec<FoodKind>,
+    /// The tick each food was put down on.
+    #[serde(default)]
+    food_ticks: Vec<usize>,
     food_count: usize,
+    #[serde(default)]
+    rules: Rules,
+    /// Pairs of cells that lead to each other.
+    #[serde(default)]
+    portals: Vec<(Vector, Vector)>,
+    /// Everything else on the board that isn't food.
+    #[serde(default)]
+    entities: Vec<Entity>,
+    seed: u64,
+    rng: Rng,
+    /// Player one's accepted direction changes, keyed by the tick they were
+    /// made before.
+    inputs: Vec<(usize, Direction)>,
+    /// Player one's presses and releases of boost, keyed by the tick they
+    /// were made before.
+    #[serde(default)]
+    boosts: Vec<(usize, bool)>,
+    /// Player one's head position and length after every tick, for
+    /// [`SnakeGame::ghost`].
+    trail: Ghost,
+    /// Ticks left on the clock of a timed game.
+    #[serde(default)]
+    time_left: Option<usize>,
+    /// Number of foods that rotted into poison.
+    #[serde(default)]
+    rotted: usize,
+    /// Rebuilt from the snakes and foods rather than saved.
+    #[serde(skip)]
+    grid: Grid,
     pub finished: bool,
-}
-
-#[derive(Debug, Serialize, Deserialize, Default)]
-pub struct GameData {
-    pub high_score: usize,
+    /// Number of ticks played.
+    pub ticks: usize,
 }
 
 #[allow(dead_code)]
 impl SnakeGame {
     pub fn new(width: usize, height: usize, food_count: usize) -> Self {
+        Self::with_seed(width, height, food_count, random_seed())
+    }
+
+    /// Creates a game whose food placement is fully determined by `seed`.
+    pub fn with_seed(width: usize, height: usize, food_count: usize, seed: u64) -> Self {
+        Self::with_players(width, height, food_count, 1, seed)
+    }
+
+    /// Creates a game for `players` snakes, spread over alternating sides of
+    /// the board.
+    pub fn with_players(
+        width: usize,
+        height: usize,
+        food_count: usize,
+        players: usize,
+        seed: u64,
+    ) -> Self {
+        let snakes = (0..players)
+            .map(|i| {
+                let y = (height * (i + 1) / (players + 1)) as isize;
+                if i % 2 == 0 {
+                    Snake::new(Vector(width as isize - 1, y), Direction::Left)
+                } else {
+                    Snake::new(Vector(0, y), Direction::Right)
+                }
+            })
+            .collect();
         let mut game = Self {
             width,
             height,
-            snake: [Vector(width as isize - 1, height as isize / 2)]
-                .into_iter()
-                .collect(),
+            snakes,
             foods: vec![],
-            food_icons: vec![],
-            direction: Direction::Left,
-            changed_direction: Direction::Left,
+            food_kinds: vec![],
+            food_ticks: vec![],
             food_count,
+            rules: Rules::default(),
+            portals: vec![],
+            entities: vec![],
+            seed,
+            rng: Rng::new(seed),
+            inputs: vec![],
+            boosts: vec![],
+            trail: Ghost::default(),
+            time_left: None,
+            rotted: 0,
+            grid: Grid::default(),
             finished: false,
+            ticks: 0,
         };
+        game.fill_grid();
         game.spawn_food();
+        game.trail.record(&game.snakes[0].body);
         game
     }
 
-    fn spawn_food(&mut self) {
+    /// The same game played by `rules` instead of the usual ones.
+    pub fn with_rules(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self.time_left = rules.time_limit;
+        if rules.moving_food {
+            // The first food was put down before any of it could move.
+            for food in self.foods.drain(..) {
+                self.grid.set(&food, Occupant::Empty);
+            }
+            self.food_kinds.clear();
+            self.food_ticks.clear();
+            self.spawn_food();
+        }
+        if rules.portals > 0 {
+            let portals = self.random_portals(rules.portals);
+            self = self.with_portals(portals);
+        }
+        self.spawn_entities();
+        self
+    }
+
+    /// The same game with `portals` in place of its own, like the ones of a
+    /// level. They must be off the snakes; food on them is moved elsewhere.
+    pub fn with_portals(mut self, portals: Vec<(Vector, Vector)>) -> Self {
+        for (a, b) in &self.portals {
+            self.grid.set(a, Occupant::Empty);
+            self.grid.set(b, Occupant::Empty);
+        }
+        self.portals = portals;
+        for (a, b) in &self.portals {
+            self.grid.set(a, Occupant::Portal);
+            self.grid.set(b, Occupant::Portal);
+        }
         let mut i = 0;
-        while self.foods.len() < self.food_count {
-            let new_food = Vector(
-                random_range(0, self.width) as isize,
-                random_range(0, self.height) as isize,
-            );
-            if self.foods.contains(&new_food) || self.snake.contains(&new_food) {
-                continue;
+        while i < self.foods.len() {
+            if self.twin(&self.foods[i]).is_some() {
+                self.remove_food(i);
+            } else {
+                i += 1;
             }
-            let food_icons = ['🥕', '🍞', '🥑', '🍒'];
-            self.food_icons
-                .push(food_icons[random_range(0, food_icons.len())]);
+        }
+        self.spawn_food();
+        self
+    }
+
+    /// `pairs` pairs of portals on free cells, none of them next to another.
+    /// They have a generator of their own, so the food comes out the same as
+    /// without them.
+    fn random_portals(&self, pairs: usize) -> Vec<(Vector, Vector)> {
+        let mut rng = Rng::new(self.seed ^ PORTAL_SALT);
+        let mut free = self.free_cells();
+        let mut cells = vec![];
+        while cells.len() < pairs * 2 && !free.is_empty() {
+            let cell = free.swap_remove(rng.range(0, free.len()));
+            free.retain(|pos| (pos.0 - cell.0).abs() + (pos.1 - cell.1).abs() > 1);
+            cells.push(cell);
+        }
+        cells
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect()
+    }
+
+    /// The other end of the portal at `pos`, if there is one.
+    fn twin(&self, pos: &Vector) -> Option<Vector> {
+        self.portals.iter().find_map(|(a, b)| {
+            if a == pos {
+                Some(*b)
+            } else if b == pos {
+                Some(*a)
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// Number of foods that rotted into poison so far.
+    pub fn rotted(&self) -> usize {
+        self.rotted
+    }
+
+    /// Ticks left before a timed game ends, or `None` if it isn't timed.
+    pub fn time_left(&self) -> Option<usize> {
+        self.time_left
+    }
+
+    /// Reads a game serialized with `serde_json`, making sure it is one that
+    /// can be carried on with.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let mut game = serde_json::from_str::<SnakeGame>(json).map_err(|e| e.to_string())?;
+        if game.width == 0 || game.height == 0 {
+            return Err("the board is empty".to_string());
+        }
+        if game
+            .width
+            .checked_mul(game.height)
+            .is_none_or(|cells| cells > MAX_CELLS)
+        {
+            return Err("the board is too big".to_string());
+        }
+        if game.snakes.is_empty() || game.snakes.iter().any(|snake| snake.body.is_empty()) {
+            return Err("every game needs a snake with at least a head".to_string());
+        }
+        if game.foods.len() != game.food_kinds.len() {
+            return Err("every food needs a kind".to_string());
+        }
+        // Games saved before food could rot don't know when it was put down.
+        if game.food_ticks.is_empty() {
+            game.food_ticks = vec![game.ticks; game.foods.len()];
+        }
+        if game.foods.len() != game.food_ticks.len() {
+            return Err("every food needs the tick it was put down on".to_string());
+        }
+        let on_board = |pos: &Vector| pos.index_in(game.width, game.height).is_some();
+        if !game
+            .portals
+            .iter()
+            .all(|(a, b)| a != b && on_board(a) && on_board(b))
+        {
+            return Err("every portal needs two cells on the board".to_string());
+        }
+        if !game
+            .snakes
+            .iter()
+            .all(|snake| snake.body.iter().all(on_board))
+        {
+            return Err("every snake needs to be on the board".to_string());
+        }
+        if !game.foods.iter().all(on_board) {
+            return Err("every food needs to be on the board".to_string());
+        }
+        if !game.entities.iter().all(|entity| on_board(&entity.pos)) {
+            return Err("every entity needs to be on the board".to_string());
+        }
+        game.fill_grid();
+        Ok(game)
+    }
+
+    /// A game showing `snapshot`, for online clients to draw. It doesn't know
+    /// the server's seed, so ticking it won't follow the server's food.
+    /// `snapshot` has to be [valid](Snapshot::is_valid).
+    pub fn from_snapshot(snapshot: &Snapshot) -> Self {
+        let snakes = snapshot
+            .snakes
+            .iter()
+            .map(|state| Snake {
+                body: state.body.iter().copied().collect(),
+                direction: state.direction,
+                queue: state.queue.iter().copied().collect(),
+                alive: state.alive,
+                ..Snake::new(state.body[0], state.direction)
+            })
+            .collect();
+        let mut trail = Ghost::default();
+        trail.record(&snapshot.snakes[0].body.iter().copied().collect());
+        let mut game = Self {
+            width: snapshot.width,
+            height: snapshot.height,
+            snakes,
+            foods: snapshot.foods.iter().map(|(pos, _)| *pos).collect(),
+            food_kinds: snapshot.foods.iter().map(|(_, kind)| *kind).collect(),
+            food_ticks: vec![snapshot.tick; snapshot.foods.len()],
+            food_count: snapshot.foods.len(),
+            rules: Rules::default(),
+            portals: vec![],
+            entities: vec![],
+            seed: 0,
+            rng: Rng::new(0),
+            inputs: vec![],
+            boosts: vec![],
+            trail,
+            time_left: None,
+            rotted: 0,
+            grid: Grid::default(),
+            finished: false,
+            ticks: snapshot.tick,
+        };
+        game.fill_grid();
+        game
+    }
+
+    /// The state the online server sends to its clients.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            tick: self.ticks,
+            width: self.width,
+            height: self.height,
+            snakes: self
+                .snakes
+                .iter()
+                .map(|snake| SnakeState {
+                    body: snake.body.iter().copied().collect(),
+                    direction: snake.direction,
+                    queue: snake.queue.iter().copied().collect(),
+                    alive: snake.alive,
+                })
+                .collect(),
+            foods: self
+                .foods
+                .iter()
+                .copied()
+                .zip(self.food_kinds.iter().copied())
+                .collect(),
+        }
+    }
+
+    /// Builds the grid from scratch out of the foods and the snakes still
+    /// alive.
+    fn fill_grid(&mut self) {
+        self.grid = Grid::new(self.width, self.height);
+        for (a, b) in &self.portals {
+            self.grid.set(a, Occupant::Portal);
+            self.grid.set(b, Occupant::Portal);
+        }
+        for food in &self.foods {
+            self.grid.set(food, Occupant::Food);
+        }
+        for entity in &self.entities {
+            self.grid.set(&entity.pos, Occupant::Entity);
+        }
+        for (player, snake) in self.snakes.iter().enumerate() {
+            if snake.alive {
+                for pos in &snake.body {
+                    self.grid.set(pos, Occupant::Snake(player));
+                }
+            }
+        }
+    }
+
+    /// Takes a snake that just died off the grid. Its body stays where it
+    /// was, but nothing runs into it anymore.
+    fn clear_snake(&mut self, player: usize) {
+        for pos in &self.snakes[player].body {
+            self.grid.set(pos, Occupant::Empty);
+        }
+    }
+
+    /// Cells with nothing in them, row by row.
+    fn free_cells(&self) -> Vec<Vector> {
+        (0..self.height as isize)
+            .flat_map(|y| (0..self.width as isize).map(move |x| Vector(x, y)))
+            .filter(|pos| self.grid.get(pos) == Some(Occupant::Empty))
+            .collect()
+    }
+
+    /// Tops the food up to `food_count`, or as close to it as the free cells
+    /// allow.
+    fn spawn_food(&mut self) {
+        // Poison doesn't count, or it would stand in for the food that rotted.
+        let mut fresh = self
+            .food_kinds
+            .iter()
+            .filter(|kind| **kind != FoodKind::Poison)
+            .count();
+        if fresh >= self.food_count {
+            return;
+        }
+        let mut free = self.free_cells();
+        let kinds = if self.rules.moving_food {
+            &FoodKind::WILD[..]
+        } else {
+            &FoodKind::STILL[..]
+        };
+        while fresh < self.food_count && !free.is_empty() {
+            let new_food = free.swap_remove(self.rng.range(0, free.len()));
+            self.grid.set(&new_food, Occupant::Food);
+            self.food_kinds.push(kinds[self.rng.range(0, kinds.len())]);
+            self.food_ticks.push(self.ticks);
             self.foods.push(new_food);
-            if i > 1000 {
-                break;
+            fresh += 1;
+        }
+    }
+
+    /// Takes the food at `i` out of the lists, but not off the grid.
+    fn remove_food(&mut self, i: usize) -> FoodKind {
+        self.foods.remove(i);
+        self.food_ticks.remove(i);
+        self.food_kinds.remove(i)
+    }
+
+    /// Turns food left uneaten for too long into poison, and takes poison
+    /// that has been lying around as long again off the board.
+    fn rot_food(&mut self) {
+        let Some(rot_ticks) = self.rules.rot_ticks else {
+            return;
+        };
+        let mut i = 0;
+        while i < self.foods.len() {
+            let age = self.ticks.saturating_sub(self.food_ticks[i]);
+            if self.food_kinds[i] != FoodKind::Poison {
+                if age >= rot_ticks {
+                    self.food_kinds[i] = FoodKind::Poison;
+                    self.rotted += 1;
+                }
+            } else if age >= rot_ticks * 2 {
+                self.grid.set(&self.foods[i], Occupant::Empty);
+                self.remove_food(i);
+                continue;
             }
             i += 1;
         }
     }
 
-    pub fn change_direction(&mut self, direction: Direction) {
-        if self.direction == direction || self.direction == direction.opposite() {
+    /// Whether the food at `i` is close to rotting.
+    fn is_rotting(&self, i: usize) -> bool {
+        self.rules.rot_ticks.is_some_and(|rot_ticks| {
+            let age = self.ticks.saturating_sub(self.food_ticks[i]);
+            self.food_kinds[i] != FoodKind::Poison && age >= rot_ticks - rot_ticks / ROTTING_SHARE
+        })
+    }
+
+    fn food_at(&self, pos: &Vector) -> Option<usize> {
+        self.foods.iter().position(|food| food == pos)
+    }
+
+    /// Moves every food whose turn it is into an empty cell next to it:
+    /// rabbits as far as they can from the closest head near them, bugs
+    /// anywhere.
+    fn move_food(&mut self) {
+        let heads = self.alive_heads();
+        let closest = |pos: &Vector| heads.iter().map(|head| distance(pos, head)).min();
+        for i in 0..self.foods.len() {
+            let kind = self.food_kinds[i];
+            if !kind
+                .period()
+                .is_some_and(|period| self.ticks.is_multiple_of(period))
+            {
+                continue;
+            }
+            let pos = self.foods[i];
+            let open = Direction::ALL
+                .into_iter()
+                .map(|direction| &pos + direction.to_vec())
+                .filter(|next| self.grid.get(next) == Some(Occupant::Empty))
+                .collect::<Vec<_>>();
+            let next = match kind {
+                FoodKind::Rabbit => closest(&pos)
+                    .filter(|current| *current <= FLEE_DISTANCE)
+                    .and_then(|current| {
+                        open.into_iter()
+                            .filter(|next| closest(next).is_some_and(|d| d > current))
+                            .max_by_key(closest)
+                    }),
+                FoodKind::Bug if !open.is_empty() => Some(open[self.rng.range(0, open.len())]),
+                _ => None,
+            };
+            if let Some(next) = next {
+                self.grid.set(&pos, Occupant::Empty);
+                self.grid.set(&next, Occupant::Food);
+                self.foods[i] = next;
+            }
+        }
+    }
+
+    /// Tops the hazards up to what the rules ask for, away from the snakes'
+    /// heads, and puts a star down if there is none and nobody is powered up.
+    fn spawn_entities(&mut self) {
+        if self.rules.hazards == 0 {
             return;
         }
-        self.changed_direction = direction;
+        let mut hazards = self
+            .entities
+            .iter()
+            .filter(|entity| entity.kind.is_hazard())
+            .count();
+        let mut star = self.snakes.iter().any(|snake| snake.powered > 0)
+            || self
+                .entities
+                .iter()
+                .any(|entity| entity.kind == EntityKind::Star);
+        // Nearly every tick has nothing to put down, so don't go looking
+        // through the whole board for where to put it.
+        if hazards >= self.rules.hazards && star {
+            return;
+        }
+        let heads = self.alive_heads();
+        let mut free = self.free_cells();
+        free.retain(|pos| {
+            heads
+                .iter()
+                .all(|head| distance(pos, head) > HAZARD_DISTANCE)
+        });
+        while (hazards < self.rules.hazards || !star) && !free.is_empty() {
+            let pos = free.swap_remove(self.rng.range(0, free.len()));
+            let kind = if star {
+                hazards += 1;
+                EntityKind::HAZARDS[self.rng.range(0, EntityKind::HAZARDS.len())]
+            } else {
+                star = true;
+                EntityKind::Star
+            };
+            let direction = Direction::ALL[self.rng.range(0, Direction::ALL.len())];
+            self.grid.set(&pos, Occupant::Entity);
+            self.entities.push(Entity {
+                kind,
+                pos,
+                direction,
+            });
+        }
     }
 
-    fn valid_pos(&self, pos: &Vector) -> bool {
-        pos.0 >= 0
-            && pos.0 < self.width as isize
-            && pos.1 >= 0
-            && pos.1 < self.height as isize
-            && !self.snake.contains(pos)
+    fn alive_heads(&self) -> Vec<Vector> {
+        self.snakes
+            .iter()
+            .filter(|snake| snake.alive)
+            .map(|snake| snake.body[0])
+            .collect()
     }
 
-    pub fn tick(&mut self) {
+    /// Whether the snakes take up every cell of the board, leaving nowhere
+    /// to go and nothing to eat.
+    fn board_filled(&self) -> bool {
+        self.foods.is_empty() && !self.grid.cells.contains(&Occupant::Empty)
+    }
+
+    pub fn change_direction(&mut self, player: usize, direction: Direction) {
         if self.finished {
             return;
         }
-        self.direction = self.changed_direction;
-        let head = &self.snake[0];
-        let new_head = head + self.direction.to_vec();
+        let Some(snake) = self.snakes.get_mut(player).filter(|snake| snake.alive) else {
+            return;
+        };
+        let last = snake.queue.back().copied().unwrap_or(snake.direction);
+        if last == direction
+            || last == direction.opposite()
+            || snake.queue.len() >= MAX_QUEUED_DIRECTIONS
+        {
+            return;
+        }
+        snake.queue.push_back(direction);
+        if player == 0 {
+            self.inputs.push((self.ticks, direction));
+        }
+    }
 
-        if !self.valid_pos(&new_head) {
-            self.finished = true;
-            alert("You're a loser!");
+    /// Starts or stops `player`'s snake boosting.
+    pub fn set_boost(&mut self, player: usize, boosting: bool) {
+        if self.finished {
             return;
         }
+        let Some(snake) = self.snakes.get_mut(player).filter(|snake| snake.alive) else {
+            return;
+        };
+        if snake.boosting == boosting {
+            return;
+        }
+        snake.boosting = boosting;
+        if player == 0 {
+            self.boosts.push((self.ticks, boosting));
+        }
+    }
 
-        if let Some(i) = self.foods.iter().position(|value| value == &new_head) {
-            self.foods.remove(i);
-            self.food_icons.remove(i);
-            self.spawn_food();
-            let _ = get_audio_element().play().unwrap_throw();
+    pub fn score(&self, player: usize) -> usize {
+        self.snakes[player].score()
+    }
+
+    /// A hash of everything that decides how the game goes on, for players
+    /// running the same game to check they haven't drifted apart. It doesn't
+    /// depend on the platform or the build, so it can be compared between
+    /// browsers, or with a game restored from JSON.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+        hasher.write_u64(self.ticks as u64);
+        hasher.write_u64(self.finished as u64);
+        hasher.write_u64(self.rng.state());
+        hasher.write_u64(self.rules.self_collision as u64);
+        hasher.write_u64(self.rules.wrap as u64);
+        hasher.write_u64(self.rules.hazards as u64);
+        hasher.write_u64(self.rules.moving_food as u64);
+        hasher.write_u64(self.rules.rot_ticks.map_or(u64::MAX, |ticks| ticks as u64));
+        hasher.write_u64(self.rules.ends_with_player_one as u64);
+        hasher.write_u64(self.time_left.map_or(u64::MAX, |ticks| ticks as u64));
+        for snake in &self.snakes {
+            hasher.write_u64(snake.alive as u64);
+            hasher.write_u64(snake.direction as u64);
+            hasher.write_u64(snake.powered as u64);
+            hasher.write_u64(snake.boosting as u64);
+            hasher.write_u64(snake.boosted_ticks as u64);
+            hasher.write_u64(snake.queue.len() as u64);
+            for direction in &snake.queue {
+                hasher.write_u64(*direction as u64);
+            }
+            hasher.write_u64(snake.body.len() as u64);
+            for pos in &snake.body {
+                hasher.write_vector(pos);
+            }
+        }
+        for (a, b) in &self.portals {
+            hasher.write_vector(a);
+            hasher.write_vector(b);
+        }
+        for (food, kind) in self.foods.iter().zip(&self.food_kinds) {
+            hasher.write_vector(food);
+            hasher.write_u64(*kind as u64);
+        }
+        if self.rules.rot_ticks.is_some() {
+            for tick in &self.food_ticks {
+                hasher.write_u64(*tick as u64);
+            }
+        }
+        for entity in &self.entities {
+            hasher.write_u64(entity.kind as u64);
+            hasher.write_vector(&entity.pos);
+            hasher.write_u64(entity.direction as u64);
+        }
+        hasher.0
+    }
+
+    /// Takes `player`'s snake off the board, as if it had died, when they
+    /// leave the game.
+    pub fn forfeit(&mut self, player: usize) {
+        if self.snakes.get(player).is_some_and(|snake| snake.alive) {
+            self.snakes[player].alive = false;
+            self.clear_snake(player);
+        }
+    }
+
+    /// Who won, once a game with more than one player is over. If it ended
+    /// with several snakes left, the longest of them wins.
+    pub fn outcome(&self) -> Option<Outcome> {
+        if !self.finished || self.snakes.len() < 2 {
+            return None;
+        }
+        Some(match self.leader() {
+            Some(player) => Outcome::Won(player),
+            None => Outcome::Draw,
+        })
+    }
+
+    /// The alive player with the longest snake, the first of them on a tie.
+    fn leader(&self) -> Option<usize> {
+        self.snakes
+            .iter()
+            .enumerate()
+            .filter(|(_, snake)| snake.alive)
+            .max_by_key(|(i, snake)| (snake.body.len(), Reverse(*i)))
+            .map(|(player, _)| player)
+    }
+
+    /// The path player one's snake took, to race against later.
+    pub fn ghost(&self) -> Ghost {
+        self.trail.clone()
+    }
+
+    /// A read-only view of the board for a computer player.
+    pub fn view(&self, player: usize) -> GameView<'_> {
+        let snake = &self.snakes[player];
+        GameView {
+            width: self.width,
+            height: self.height,
+            snake: &snake.body,
+            foods: self
+                .foods
+                .iter()
+                .zip(&self.food_kinds)
+                .filter(|(_, kind)| **kind != FoodKind::Poison)
+                .map(|(food, _)| *food)
+                .collect(),
+            direction: snake.direction,
+            portals: self.portals.iter().flat_map(|(a, b)| [*a, *b]).collect(),
+            hazards: self
+                .entities
+                .iter()
+                .filter(|entity| entity.kind.is_hazard() && snake.powered == 0)
+                .map(|entity| entity.pos)
+                .chain(
+                    self.foods
+                        .iter()
+                        .zip(&self.food_kinds)
+                        .filter(|(_, kind)| **kind == FoodKind::Poison)
+                        .map(|(food, _)| *food),
+                )
+                .collect(),
+            others: self
+                .snakes
+                .iter()
+                .enumerate()
+                .filter(|(i, other)| *i != player && other.alive)
+                .map(|(_, other)| &other.body)
+                .collect(),
+        }
+    }
+
+    /// Everything needed to play this game back tick for tick.
+    pub fn replay(&self) -> Replay {
+        Replay {
+            seed: self.seed,
+            width: self.width,
+            height: self.height,
+            food_count: self.food_count,
+            rules: self.rules,
+            portals: self.portals.clone(),
+            score: self.score(0),
+            inputs: self.inputs.clone(),
+            boosts: self.boosts.clone(),
+        }
+    }
+
+    /// The direction from `from` to the cell `to` next to it, which may be on
+    /// the other side of the board if the edges wrap.
+    pub(crate) fn direction_between(&self, from: &Vector, to: &Vector) -> Option<Direction> {
+        Direction::between(from, to).or_else(|| {
+            if !self.rules.wrap {
+                return None;
+            }
+            let (width, height) = (self.width as isize, self.height as isize);
+            match (to.0 - from.0, to.1 - from.1) {
+                (dx, 0) if dx == width - 1 => Some(Direction::Left),
+                (dx, 0) if dx == 1 - width => Some(Direction::Right),
+                (0, dy) if dy == height - 1 => Some(Direction::Up),
+                (0, dy) if dy == 1 - height => Some(Direction::Down),
+                _ => None,
+            }
+        })
+    }
+
+    fn wrap(&self, pos: Vector) -> Vector {
+        if !self.rules.wrap {
+            return pos;
+        }
+        Vector(
+            pos.0.rem_euclid(self.width as isize),
+            pos.1.rem_euclid(self.height as isize),
+        )
+    }
+
+    /// Where a head at `pos` moving in `direction` ends up. Entering a portal
+    /// takes it out past the portal's twin, still heading the same way.
+    fn next_head(&self, pos: &Vector, direction: Direction) -> Vector {
+        let head = self.wrap(pos + direction.to_vec());
+        match self.twin(&head) {
+            Some(twin) => self.wrap(&twin + direction.to_vec()),
+            None => head,
+        }
+    }
+
+    /// Returns what `player`'s snake would die of if its head moved to `pos`,
+    /// given where every snake's head is about to move.
+    fn collision(
+        &self,
+        player: usize,
+        pos: &Vector,
+        heads: &[Option<Vector>],
+    ) -> Option<DeathCause> {
+        match self.grid.get(pos) {
+            // Coming out of a portal straight into another one counts as
+            // hitting a wall.
+            None | Some(Occupant::Portal) => Some(DeathCause::Wall),
+            Some(Occupant::Snake(other)) if other == player => {
+                (self.rules.self_collision == SelfCollision::Die).then_some(DeathCause::OwnBody)
+            }
+            Some(Occupant::Snake(_)) => Some(DeathCause::OtherSnake),
+            Some(Occupant::Food)
+                if self.snakes[player].body.len() <= POISON_LOSS
+                    && self
+                        .food_at(pos)
+                        .is_some_and(|i| self.food_kinds[i] == FoodKind::Poison) =>
+            {
+                Some(DeathCause::Poison)
+            }
+            Some(Occupant::Entity)
+                if self.snakes[player].powered == 0
+                    && self
+                        .entity_at(pos)
+                        .is_some_and(|i| self.entities[i].kind.is_hazard()) =>
+            {
+                Some(DeathCause::Hazard)
+            }
+            _ if heads
+                .iter()
+                .enumerate()
+                .any(|(i, head)| i != player && head.as_ref() == Some(pos)) =>
+            {
+                Some(DeathCause::HeadOn)
+            }
+            _ => None,
+        }
+    }
+
+    fn entity_at(&self, pos: &Vector) -> Option<usize> {
+        self.entities.iter().position(|entity| &entity.pos == pos)
+    }
+
+    /// Moves every entity whose turn it is. They step into empty cells, or
+    /// into a snake's head, which kills the snake unless it is powered up, in
+    /// which case it eats the entity.
+    fn move_entities(&mut self, events: &mut Vec<GameEvent>) {
+        let mut i = 0;
+        while i < self.entities.len() {
+            let entity = self.entities[i];
+            if !entity
+                .kind
+                .period()
+                .is_some_and(|period| self.ticks.is_multiple_of(period))
+            {
+                i += 1;
+                continue;
+            }
+            let heads = self.alive_heads();
+            let is_open = |pos: &Vector| match self.grid.get(pos) {
+                Some(Occupant::Empty) => true,
+                Some(Occupant::Snake(player)) => self.snakes[player].body[0] == *pos,
+                _ => false,
+            };
+            let Some((pos, direction)) = entity.next_move(is_open, &heads) else {
+                i += 1;
+                continue;
+            };
+            if let Some(Occupant::Snake(player)) = self.grid.get(&pos) {
+                if self.snakes[player].powered > 0 {
+                    self.grid.set(&entity.pos, Occupant::Empty);
+                    self.entities.remove(i);
+                    events.push(GameEvent::Devoured {
+                        player,
+                        kind: entity.kind,
+                    });
+                    continue;
+                }
+                self.snakes[player].alive = false;
+                self.clear_snake(player);
+                events.push(GameEvent::Died {
+                    player,
+                    cause: DeathCause::Hazard,
+                });
+            }
+            self.grid.set(&entity.pos, Occupant::Empty);
+            self.grid.set(&pos, Occupant::Entity);
+            self.entities[i] = Entity {
+                pos,
+                direction,
+                ..entity
+            };
+            i += 1;
+        }
+    }
+
+    pub fn tick(&mut self) -> Vec<GameEvent> {
+        let mut events = vec![];
+        if self.finished {
+            return events;
+        }
+        self.move_entities(&mut events);
+        self.move_food();
+        for (player, snake) in self.snakes.iter_mut().enumerate() {
+            if !snake.alive {
+                continue;
+            }
+            if let Some(direction) = snake.queue.pop_front() {
+                snake.direction = direction;
+                events.push(GameEvent::Turned { player, direction });
+            }
+        }
+        let heads = self
+            .snakes
+            .iter()
+            .map(|snake| {
+                snake
+                    .alive
+                    .then(|| self.next_head(&snake.body[0], snake.direction))
+            })
+            .collect::<Vec<_>>();
+
+        // Every snake moves at once, so find out who dies before moving anyone.
+        let deaths = heads
+            .iter()
+            .enumerate()
+            .map(|(player, head)| {
+                head.as_ref()
+                    .and_then(|head| self.collision(player, head, &heads))
+            })
+            .collect::<Vec<_>>();
+
+        for (player, head) in heads.into_iter().enumerate() {
+            let Some(head) = head else {
+                continue;
+            };
+            let snake = &mut self.snakes[player];
+            if let Some(cause) = deaths[player] {
+                snake.alive = false;
+                self.clear_snake(player);
+                events.push(GameEvent::Died { player, cause });
+                continue;
+            }
+            snake.ticks += 1;
+            snake.powered = snake.powered.saturating_sub(1);
+            *snake.direction_ticks.entry(snake.direction).or_default() += 1;
+            if self.grid.get(&head) == Some(Occupant::Food) {
+                let i = self.foods.iter().position(|food| food == &head).unwrap();
+                self.foods.remove(i);
+                self.food_ticks.remove(i);
+                let kind = self.food_kinds.remove(i);
+                *snake.food_eaten.entry(kind).or_default() += 1;
+                if kind == FoodKind::Poison {
+                    // Not growing costs the tail, and the poison some more.
+                    // The snake is long enough, or it would have died.
+                    for pos in snake.body.drain(snake.body.len() - POISON_LOSS - 1..) {
+                        self.grid.set(&pos, Occupant::Empty);
+                    }
+                    events.push(GameEvent::Poisoned {
+                        player,
+                        lost: POISON_LOSS,
+                    });
+                } else {
+                    if let Some(time_left) = &mut self.time_left {
+                        *time_left += kind.bonus_ticks();
+                    }
+                    events.push(GameEvent::Ate { player, kind });
+                }
+            } else if self.grid.get(&head) == Some(Occupant::Entity) {
+                let i = self
+                    .entities
+                    .iter()
+                    .position(|entity| entity.pos == head)
+                    .unwrap();
+                let entity = self.entities.remove(i);
+                if entity.kind == EntityKind::Star {
+                    snake.powered = POWER_TICKS;
+                    events.push(GameEvent::PoweredUp { player });
+                } else {
+                    events.push(GameEvent::Devoured {
+                        player,
+                        kind: entity.kind,
+                    });
+                }
+                // Entities don't make the snake any longer.
+                if let Some(tail) = snake.body.pop_back() {
+                    self.grid.set(&tail, Occupant::Empty);
+                }
+            } else if self.grid.get(&head) == Some(Occupant::Snake(player)) {
+                // The head moves into the bitten cell, so that one isn't lost.
+                // Biting the tail just chases it, like a normal move.
+                let bitten = snake.body.iter().position(|pos| pos == &head).unwrap();
+                let lost = snake.body.len() - bitten - 1;
+                for pos in snake.body.drain(bitten..) {
+                    self.grid.set(&pos, Occupant::Empty);
+                }
+                if lost > 0 {
+                    events.push(GameEvent::Cut { player, lost });
+                }
+            } else if let Some(tail) = snake.body.pop_back() {
+                self.grid.set(&tail, Occupant::Empty);
+            }
+            snake.body.push_front(head);
+            snake.peak_length = snake.peak_length.max(snake.body.len());
+            self.grid.set(&head, Occupant::Snake(player));
+            if snake.is_boosting() {
+                snake.boosted_ticks += 1;
+                if snake.boosted_ticks.is_multiple_of(BOOST_COST) {
+                    if let Some(tail) = snake.body.pop_back() {
+                        self.grid.set(&tail, Occupant::Empty);
+                    }
+                    events.push(GameEvent::Shed { player });
+                }
+            }
+        }
+
+        let alive = self.snakes.iter().filter(|snake| snake.alive).count();
+        let over = if self.rules.ends_with_player_one {
+            // The game revolves around player one: it's over once they die
+            // or have outlasted everyone else.
+            !self.snakes[0].alive || (self.snakes.len() > 1 && alive == 1)
         } else {
-            self.snake.pop_back();
+            alive == 0 || (self.snakes.len() > 1 && alive == 1)
         };
-        self.snake.push_front(new_head);
+        if over {
+            self.finished = true;
+            return events;
+        }
+        self.ticks += 1;
+        self.rot_food();
+        self.spawn_food();
+        self.spawn_entities();
+        self.trail.record(&self.snakes[0].body);
+        if self.board_filled() {
+            self.finished = true;
+            if let Some(player) = self.leader() {
+                events.push(GameEvent::FilledBoard { player });
+            }
+            return events;
+        }
+        if let Some(time_left) = &mut self.time_left {
+            *time_left = time_left.saturating_sub(1);
+            if *time_left == 0 {
+                self.finished = true;
+                events.push(GameEvent::TimeUp);
+            }
+        }
+        events
     }
 }
 
 impl Renderable for SnakeGame {
-    fn renderer_data(&self) -> HashMap<Vector, char> {
-        let mut data = HashMap::new();
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let pos = Vector(x as isize, y as isize);
-                let char: char;
-                if self.snake.contains(&pos) {
-                    if pos == self.snake[0] {
-                        char = '🟨';
-                    } else {
-                        char = '⬛';
+    fn frame(&self) -> Frame {
+        let mut frame = Frame::new(self.width, self.height);
+        frame.hud.time_left = self.time_left;
+        for (pair, (a, b)) in self.portals.iter().enumerate() {
+            frame.set(a, Cell::Portal(pair));
+            frame.set(b, Cell::Portal(pair));
+        }
+        for (i, (food, kind)) in self.foods.iter().zip(&self.food_kinds).enumerate() {
+            let cell = if self.is_rotting(i) {
+                Cell::Rotting(*kind)
+            } else {
+                Cell::Food(*kind)
+            };
+            frame.set(food, cell);
+        }
+        for entity in &self.entities {
+            frame.set(&entity.pos, Cell::Entity(entity.kind));
+        }
+        for (player, snake) in self.snakes.iter().enumerate() {
+            frame.hud.scores.push(snake.score());
+            if !snake.alive {
+                continue;
+            }
+            let toward = |from: &Vector, to: &Vector| {
+                self.direction_between(from, to).unwrap_or(snake.direction)
+            };
+            let last = snake.body.len() - 1;
+            for (i, pos) in snake.body.iter().enumerate() {
+                let cell = if i == 0 {
+                    Cell::Head {
+                        player,
+                        facing: snake.direction,
+                    }
+                } else if i == last {
+                    Cell::Tail {
+                        player,
+                        toward_head: toward(pos, &snake.body[i - 1]),
                     }
-                } else if let Some(i) = self.foods.iter().position(|food| food == &pos) {
-                    char = self.food_icons[i];
                 } else {
-                    char = ' ';
-                }
-                data.insert(pos, char);
+                    Cell::Body {
+                        player,
+                        toward_head: toward(pos, &snake.body[i - 1]),
+                        toward_tail: toward(pos, &snake.body[i + 1]),
+                    }
+                };
+                frame.set(pos, cell);
+            }
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::StrategyKind;
+
+    /// A one player game on an empty 8x8 board, with the snake laid out
+    /// along `body` from the head and about to move in `direction`.
+    fn game_with(rules: Rules, body: &[(isize, isize)], direction: Direction) -> SnakeGame {
+        let mut game = SnakeGame::with_seed(8, 8, 0, 0).with_rules(rules);
+        let snake = &mut game.snakes[0];
+        snake.body = body.iter().map(|&(x, y)| Vector(x, y)).collect();
+        snake.peak_length = body.len();
+        snake.direction = direction;
+        game.fill_grid();
+        game
+    }
+
+    #[test]
+    fn biting_the_body_cuts_it_off_there() {
+        // A hook whose head turns down into its own middle.
+        let body = [(2, 1), (3, 1), (3, 2), (2, 2), (1, 2), (0, 2)];
+        let mut game = game_with(Rules::ZEN, &body, Direction::Down);
+        let events = game.tick();
+        assert!(events.contains(&GameEvent::Cut { player: 0, lost: 2 }));
+        assert!(game.snakes[0].alive);
+        assert_eq!(
+            game.snakes[0].body,
+            [Vector(2, 2), Vector(2, 1), Vector(3, 1), Vector(3, 2)]
+        );
+        // The cells cut off are free again.
+        assert_eq!(game.grid.get(&Vector(1, 2)), Some(Occupant::Empty));
+        assert_eq!(game.grid.get(&Vector(0, 2)), Some(Occupant::Empty));
+    }
+
+    #[test]
+    fn biting_the_tail_only_chases_it() {
+        let body = [(1, 1), (2, 1), (2, 2), (1, 2)];
+        let mut game = game_with(Rules::ZEN, &body, Direction::Down);
+        let events = game.tick();
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, GameEvent::Cut { .. })));
+        assert_eq!(game.snakes[0].body.len(), 4);
+        assert_eq!(game.snakes[0].body[0], Vector(1, 2));
+    }
+
+    #[test]
+    fn biting_the_body_across_the_wrapped_edge_cuts() {
+        // Going up from the top row comes back in at the bottom, where the
+        // snake's body carries on.
+        let body = [(0, 0), (1, 0), (1, 7), (0, 7), (7, 7)];
+        let mut game = game_with(Rules::ZEN, &body, Direction::Up);
+        let events = game.tick();
+        assert!(events.contains(&GameEvent::Cut { player: 0, lost: 1 }));
+        assert_eq!(
+            game.snakes[0].body,
+            [Vector(0, 7), Vector(0, 0), Vector(1, 0), Vector(1, 7)]
+        );
+    }
+
+    #[test]
+    fn biting_the_body_without_cut_rules_kills() {
+        let body = [(2, 1), (3, 1), (3, 2), (2, 2), (1, 2), (0, 2)];
+        let mut game = game_with(Rules::default(), &body, Direction::Down);
+        let events = game.tick();
+        assert!(events.contains(&GameEvent::Died {
+            player: 0,
+            cause: DeathCause::OwnBody
+        }));
+        assert!(game.finished);
+    }
+
+    /// Puts food of `kind` down at `pos`.
+    fn add_food(game: &mut SnakeGame, kind: FoodKind, pos: Vector) {
+        game.foods.push(pos);
+        game.food_kinds.push(kind);
+        game.food_ticks.push(game.ticks);
+        game.grid.set(&pos, Occupant::Food);
+    }
+
+    /// Puts poison down at `pos`.
+    fn add_poison(game: &mut SnakeGame, pos: Vector) {
+        add_food(game, FoodKind::Poison, pos);
+    }
+
+    #[test]
+    fn poison_takes_cells_off_the_tail() {
+        let body = [(3, 3), (4, 3), (5, 3), (6, 3), (7, 3)];
+        let mut game = game_with(Rules::default(), &body, Direction::Left);
+        add_poison(&mut game, Vector(2, 3));
+        let events = game.tick();
+        assert!(events.contains(&GameEvent::Poisoned {
+            player: 0,
+            lost: POISON_LOSS
+        }));
+        assert_eq!(game.snakes[0].body, [Vector(2, 3), Vector(3, 3)]);
+        // The stats still see how long it was.
+        assert_eq!(game.snakes[0].peak_length(), 5);
+        assert!(game.foods.is_empty());
+        for x in 4..8 {
+            assert_eq!(game.grid.get(&Vector(x, 3)), Some(Occupant::Empty));
+        }
+    }
+
+    #[test]
+    fn poison_can_leave_only_the_head() {
+        let body = [(3, 3), (4, 3), (5, 3), (6, 3)];
+        let mut game = game_with(Rules::default(), &body, Direction::Left);
+        add_poison(&mut game, Vector(2, 3));
+        game.tick();
+        assert!(game.snakes[0].alive);
+        assert_eq!(game.snakes[0].body, [Vector(2, 3)]);
+    }
+
+    #[test]
+    fn poison_kills_a_snake_too_short_to_lose_the_cells() {
+        let body = [(3, 3), (4, 3), (5, 3)];
+        let mut game = game_with(Rules::default(), &body, Direction::Left);
+        add_poison(&mut game, Vector(2, 3));
+        let events = game.tick();
+        assert!(events.contains(&GameEvent::Died {
+            player: 0,
+            cause: DeathCause::Poison
+        }));
+        assert!(game.finished);
+    }
+
+    #[test]
+    fn food_rots_into_poison_and_then_away() {
+        let rules = Rules {
+            rot_ticks: Some(5),
+            ..Rules::default()
+        };
+        let mut game = SnakeGame::with_seed(8, 8, 1, 0).with_rules(rules);
+        let food = game.foods[0];
+        game.ticks = 4;
+        game.rot_food();
+        assert_ne!(game.food_kinds[0], FoodKind::Poison);
+        assert!(game.is_rotting(0));
+        game.ticks = 5;
+        game.rot_food();
+        assert_eq!(game.food_kinds[0], FoodKind::Poison);
+        assert_eq!(game.rotted(), 1);
+        // Poison doesn't count as food, so fresh food joins it.
+        game.spawn_food();
+        assert_eq!(game.foods.len(), 2);
+        assert_ne!(game.food_kinds[1], FoodKind::Poison);
+        game.ticks = 10;
+        game.rot_food();
+        assert_eq!(game.foods.len(), 1);
+        assert_ne!(game.foods[0], food);
+        assert_eq!(game.grid.get(&food), Some(Occupant::Empty));
+    }
+
+    #[test]
+    fn spawn_food_only_uses_free_cells() {
+        for seed in 0..20 {
+            // Far more food than the board has room for.
+            let game = SnakeGame::with_seed(3, 2, 10, seed);
+            let mut cells = game.foods.clone();
+            cells.extend(&game.snakes[0].body);
+            cells.sort_by_key(|pos| (pos.0, pos.1));
+            cells.dedup();
+            assert_eq!(game.foods.len(), 5);
+            assert_eq!(cells.len(), 6, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn spawn_food_on_a_full_board_places_nothing() {
+        let mut game = SnakeGame::with_seed(3, 1, 1, 0);
+        game.snakes[0].body = [Vector(0, 0), Vector(1, 0), Vector(2, 0)]
+            .into_iter()
+            .collect();
+        game.foods.clear();
+        game.food_kinds.clear();
+        game.food_ticks.clear();
+        game.fill_grid();
+        game.spawn_food();
+        assert!(game.foods.is_empty());
+        assert!(game.board_filled());
+    }
+
+    #[test]
+    fn eating_the_last_free_cell_wins() {
+        // The snake starts at the right end, with the food to its left.
+        let mut game = SnakeGame::with_seed(2, 1, 1, 0);
+        assert_eq!(game.foods, vec![Vector(0, 0)]);
+        let events = game.tick();
+        assert!(game.finished);
+        assert!(events.contains(&GameEvent::FilledBoard { player: 0 }));
+        assert_eq!(game.score(0), 1);
+    }
+
+    #[test]
+    fn filling_the_board_ends_the_game() {
+        for seed in 0..5 {
+            let mut game = SnakeGame::with_seed(6, 6, 3, seed);
+            let mut strategy = StrategyKind::Hamiltonian.build();
+            let mut events = vec![];
+            while !game.finished && game.ticks < 10_000 {
+                game.change_direction(0, strategy.next_direction(&game.view(0)));
+                events = game.tick();
+            }
+            assert!(
+                events.contains(&GameEvent::FilledBoard { player: 0 }),
+                "seed {}",
+                seed
+            );
+            assert_eq!(game.score(0), 35);
+        }
+    }
+
+    /// Puts an entity of `kind` down at `pos`, about to move in `direction`.
+    fn add_entity(game: &mut SnakeGame, kind: EntityKind, pos: Vector, direction: Direction) {
+        game.entities.push(Entity {
+            kind,
+            pos,
+            direction,
+        });
+        game.grid.set(&pos, Occupant::Entity);
+    }
+
+    #[test]
+    fn a_hazard_stepping_into_a_head_kills_it() {
+        let body = [(3, 3), (4, 3)];
+        let mut game = game_with(Rules::default(), &body, Direction::Left);
+        // Blocks move on even ticks, so this one goes first.
+        add_entity(&mut game, EntityKind::Block, Vector(3, 4), Direction::Up);
+        let events = game.tick();
+        assert!(events.contains(&GameEvent::Died {
+            player: 0,
+            cause: DeathCause::Hazard
+        }));
+        assert!(game.finished);
+    }
+
+    #[test]
+    fn running_into_a_hazard_kills() {
+        let body = [(3, 3), (4, 3)];
+        let mut game = game_with(Rules::default(), &body, Direction::Left);
+        game.ticks = 1;
+        add_entity(&mut game, EntityKind::Block, Vector(2, 3), Direction::Up);
+        let events = game.tick();
+        assert!(events.contains(&GameEvent::Died {
+            player: 0,
+            cause: DeathCause::Hazard
+        }));
+    }
+
+    #[test]
+    fn eating_a_star_powers_the_snake_up() {
+        let body = [(3, 3), (4, 3), (5, 3)];
+        let mut game = game_with(Rules::default(), &body, Direction::Left);
+        add_entity(&mut game, EntityKind::Star, Vector(2, 3), Direction::Up);
+        let events = game.tick();
+        assert!(events.contains(&GameEvent::PoweredUp { player: 0 }));
+        assert_eq!(game.snakes[0].powered, POWER_TICKS);
+        assert!(game.entities.is_empty());
+        // Stars aren't food.
+        assert_eq!(game.snakes[0].body.len(), 3);
+    }
+
+    #[test]
+    fn a_powered_snake_eats_hazards() {
+        let body = [(3, 3), (4, 3), (5, 3)];
+        let mut game = game_with(Rules::default(), &body, Direction::Left);
+        game.snakes[0].powered = 10;
+        game.ticks = 1;
+        add_entity(&mut game, EntityKind::Block, Vector(2, 3), Direction::Up);
+        let events = game.tick();
+        assert!(events.contains(&GameEvent::Devoured {
+            player: 0,
+            kind: EntityKind::Block
+        }));
+        assert!(game.snakes[0].alive);
+        assert!(game.entities.is_empty());
+        assert_eq!(game.snakes[0].body.len(), 3);
+    }
+
+    #[test]
+    fn a_hazard_stepping_into_a_powered_head_is_eaten() {
+        let body = [(3, 3), (4, 3)];
+        let mut game = game_with(Rules::default(), &body, Direction::Left);
+        game.snakes[0].powered = 10;
+        add_entity(&mut game, EntityKind::Block, Vector(3, 4), Direction::Up);
+        let events = game.tick();
+        assert!(events.contains(&GameEvent::Devoured {
+            player: 0,
+            kind: EntityKind::Block
+        }));
+        assert!(game.snakes[0].alive);
+        assert!(game.entities.is_empty());
+        assert_eq!(game.grid.get(&Vector(3, 4)), Some(Occupant::Empty));
+    }
+
+    #[test]
+    fn entering_a_portal_comes_out_past_its_twin() {
+        let body = [(3, 3), (4, 3)];
+        let mut game = game_with(Rules::default(), &body, Direction::Left)
+            .with_portals(vec![(Vector(2, 3), Vector(6, 1))]);
+        game.tick();
+        assert!(game.snakes[0].alive);
+        assert_eq!(game.snakes[0].body, [Vector(5, 1), Vector(3, 3)]);
+        // The portals stay where they are.
+        assert_eq!(game.grid.get(&Vector(2, 3)), Some(Occupant::Portal));
+        assert_eq!(game.grid.get(&Vector(6, 1)), Some(Occupant::Portal));
+    }
+
+    #[test]
+    fn coming_out_into_another_portal_hits_a_wall() {
+        let body = [(3, 3), (4, 3)];
+        let mut game = game_with(Rules::default(), &body, Direction::Left).with_portals(vec![
+            (Vector(2, 3), Vector(6, 1)),
+            (Vector(5, 1), Vector(0, 6)),
+        ]);
+        let events = game.tick();
+        assert!(events.contains(&GameEvent::Died {
+            player: 0,
+            cause: DeathCause::Wall
+        }));
+    }
+
+    #[test]
+    fn rabbits_run_away_from_heads() {
+        let body = [(3, 3), (4, 3)];
+        let mut game = game_with(Rules::default(), &body, Direction::Down);
+        add_food(&mut game, FoodKind::Rabbit, Vector(1, 3));
+        game.move_food();
+        let rabbit = game.foods[0];
+        assert!(distance(&rabbit, &Vector(3, 3)) > 2);
+        assert_eq!(game.grid.get(&rabbit), Some(Occupant::Food));
+        assert_eq!(game.grid.get(&Vector(1, 3)), Some(Occupant::Empty));
+    }
+
+    #[test]
+    fn rabbits_stay_put_with_nowhere_farther_to_go() {
+        let body = [(2, 3), (3, 3)];
+        let mut game = game_with(Rules::default(), &body, Direction::Down);
+        add_food(&mut game, FoodKind::Rabbit, Vector(2, 1));
+        // Every way but back toward the head is taken.
+        for pos in [Vector(2, 0), Vector(1, 1), Vector(3, 1)] {
+            add_entity(&mut game, EntityKind::Star, pos, Direction::Up);
+        }
+        game.move_food();
+        assert_eq!(game.foods, [Vector(2, 1)]);
+    }
+
+    #[test]
+    fn rabbits_far_from_any_head_stay_put() {
+        let body = [(0, 0), (1, 0)];
+        let mut game = game_with(Rules::default(), &body, Direction::Down);
+        add_food(&mut game, FoodKind::Rabbit, Vector(5, 5));
+        game.move_food();
+        assert_eq!(game.foods, [Vector(5, 5)]);
+    }
+
+    #[test]
+    fn bugs_only_move_onto_free_cells() {
+        let body = [(5, 5), (6, 5)];
+        let mut game = game_with(Rules::default(), &body, Direction::Left);
+        add_food(&mut game, FoodKind::Bug, Vector(1, 1));
+        add_food(&mut game, FoodKind::Carrot, Vector(0, 1));
+        for pos in [Vector(1, 0), Vector(2, 1)] {
+            add_entity(&mut game, EntityKind::Star, pos, Direction::Up);
+        }
+        game.move_food();
+        assert_eq!(game.foods, [Vector(1, 2), Vector(0, 1)]);
+        assert_eq!(game.grid.get(&Vector(1, 1)), Some(Occupant::Empty));
+        // With nowhere free left, it stays where it is.
+        add_entity(&mut game, EntityKind::Star, Vector(1, 1), Direction::Up);
+        for pos in [Vector(0, 2), Vector(2, 2), Vector(1, 3)] {
+            add_entity(&mut game, EntityKind::Star, pos, Direction::Up);
+        }
+        game.ticks = 2;
+        game.move_food();
+        assert_eq!(game.foods[0], Vector(1, 2));
+    }
+
+    #[test]
+    fn boosting_sheds_a_cell_every_so_often() {
+        let body = [(3, 3), (4, 3), (5, 3)];
+        let mut game = game_with(Rules::ZEN, &body, Direction::Left);
+        game.set_boost(0, true);
+        let mut sheds = vec![];
+        for tick in 1..=4 * BOOST_COST {
+            if game.tick().contains(&GameEvent::Shed { player: 0 }) {
+                sheds.push(tick);
             }
         }
-        data
+        // Down to the head, then there's nothing left to shed.
+        assert_eq!(sheds, [BOOST_COST, 2 * BOOST_COST]);
+        assert_eq!(game.snakes[0].body.len(), 1);
+        assert!(game.snakes[0].alive);
+        assert!(!game.snakes[0].is_boosting());
+    }
+
+    /// Like `game_with`, but with a second player laid out along `other`.
+    fn two_player_game_with(
+        body: &[(isize, isize)],
+        direction: Direction,
+        other: &[(isize, isize)],
+        other_direction: Direction,
+    ) -> SnakeGame {
+        let mut game = SnakeGame::with_players(8, 8, 0, 2, 0);
+        for (snake, body, direction) in [(0, body, direction), (1, other, other_direction)] {
+            let snake = &mut game.snakes[snake];
+            snake.body = body.iter().map(|&(x, y)| Vector(x, y)).collect();
+            snake.peak_length = body.len();
+            snake.direction = direction;
+        }
+        game.fill_grid();
+        game
+    }
+
+    #[test]
+    fn meeting_head_on_is_a_draw() {
+        let mut game = two_player_game_with(
+            &[(2, 3), (1, 3)],
+            Direction::Right,
+            &[(4, 3), (5, 3)],
+            Direction::Left,
+        );
+        let events = game.tick();
+        for player in 0..2 {
+            assert!(events.contains(&GameEvent::Died {
+                player,
+                cause: DeathCause::HeadOn
+            }));
+        }
+        assert_eq!(game.outcome(), Some(Outcome::Draw));
+    }
+
+    #[test]
+    fn swapping_heads_is_a_draw() {
+        let mut game = two_player_game_with(
+            &[(3, 3), (2, 3)],
+            Direction::Right,
+            &[(4, 3), (5, 3)],
+            Direction::Left,
+        );
+        game.tick();
+        assert!(game.snakes.iter().all(|snake| !snake.alive));
+        assert_eq!(game.outcome(), Some(Outcome::Draw));
+    }
+
+    fn timed(ticks: usize) -> Rules {
+        Rules {
+            time_limit: Some(ticks),
+            ..Rules::default()
+        }
+    }
+
+    #[test]
+    fn cherries_add_to_the_clock() {
+        let body = [(3, 3), (4, 3)];
+        let mut game = game_with(timed(5), &body, Direction::Left);
+        add_food(&mut game, FoodKind::Cherry, Vector(2, 3));
+        let events = game.tick();
+        assert!(events.contains(&GameEvent::Ate {
+            player: 0,
+            kind: FoodKind::Cherry
+        }));
+        assert_eq!(
+            game.time_left(),
+            Some(5 + FoodKind::Cherry.bonus_ticks() - 1)
+        );
+    }
+
+    #[test]
+    fn running_out_of_time_ends_the_game() {
+        let body = [(7, 3), (7, 4)];
+        let mut game = game_with(timed(3), &body, Direction::Left);
+        for _ in 0..2 {
+            assert!(!game.tick().contains(&GameEvent::TimeUp));
+            assert!(!game.finished);
+        }
+        assert!(game.tick().contains(&GameEvent::TimeUp));
+        assert!(game.finished);
+        assert_eq!(game.time_left(), Some(0));
+        assert!(game.snakes[0].alive);
+        // Nothing moves once the game is over.
+        let body = game.snakes[0].body.clone();
+        assert!(game.tick().is_empty());
+        assert_eq!(game.snakes[0].body, body);
     }
 }
@@ -0,0 +1,190 @@
+use crate::ai::Difficulty;
+use crate::helper::style;
+use crate::save::GameData;
+use crate::snake::{DeathCause, Direction, FoodKind, SnakeGame};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+use web_sys::{window, HtmlElement};
+
+/// Cumulative statistics across every game played in this browser.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Stats {
+    pub games_played: usize,
+    pub food_eaten: HashMap<FoodKind, usize>,
+    pub total_ticks: usize,
+    pub longest_snake: usize,
+    pub deaths: HashMap<DeathCause, usize>,
+    pub total_score: usize,
+    pub direction_ticks: HashMap<Direction, usize>,
+    pub boards_filled: usize,
+    pub foods_rotted: usize,
+}
+
+impl Stats {
+    /// Adds player one's finished game. `cause` is `None` if the snake filled
+    /// the board.
+    pub fn record_game(&mut self, game: &SnakeGame, cause: Option<DeathCause>) {
+        let snake = &game.snakes[0];
+        self.games_played += 1;
+        for (kind, count) in &snake.food_eaten {
+            *self.food_eaten.entry(*kind).or_default() += count;
+        }
+        self.total_ticks += snake.ticks;
+        self.longest_snake = self.longest_snake.max(snake.peak_length());
+        match cause {
+            Some(cause) => *self.deaths.entry(cause).or_default() += 1,
+            None => self.boards_filled += 1,
+        }
+        self.foods_rotted += game.rotted();
+        self.total_score += snake.score();
+        for (direction, count) in &snake.direction_ticks {
+            *self.direction_ticks.entry(*direction).or_default() += count;
+        }
+    }
+
+    pub fn average_score(&self) -> f64 {
+        if self.games_played == 0 {
+            0.0
+        } else {
+            self.total_score as f64 / self.games_played as f64
+        }
+    }
+
+    pub fn most_used_direction(&self) -> Option<Direction> {
+        self.direction_ticks
+            .iter()
+            .max_by_key(|(_, count)| **count)
+            .map(|(direction, _)| *direction)
+    }
+}
+
+/// Results of games against computer opponents of one difficulty.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy)]
+#[serde(default)]
+pub struct VersusRecord {
+    pub wins: usize,
+    pub losses: usize,
+    pub draws: usize,
+}
+
+impl VersusRecord {
+    pub fn games(&self) -> usize {
+        self.wins + self.losses + self.draws
+    }
+}
+
+pub fn show_stats_screen(data: &GameData) {
+    let stats = &data.stats;
+    let window = window().unwrap_throw();
+    let document = window.document().unwrap_throw();
+    if let Some(screen) = document.get_element_by_id("stats-screen") {
+        screen.remove();
+    }
+    let screen = document
+        .create_element("div")
+        .unwrap_throw()
+        .dyn_into::<HtmlElement>()
+        .unwrap_throw();
+    screen.set_id("stats-screen");
+    style!(screen {
+        "position": "fixed";
+        "top": "50%";
+        "left": "50%";
+        "transform": "translate(-50%, -50%)";
+        "padding": "16px 24px";
+        "background": "white";
+        "border": "1px solid black";
+        "font-family": "Roboto, sans serif";
+        "cursor": "pointer";
+    });
+
+    let mut rows = vec![
+        ("games played".to_string(), stats.games_played.to_string()),
+        ("ticks survived".to_string(), stats.total_ticks.to_string()),
+        ("longest snake".to_string(), stats.longest_snake.to_string()),
+        (
+            "average score".to_string(),
+            format!("{:.1}", stats.average_score()),
+        ),
+        (
+            "deaths by wall".to_string(),
+            stats
+                .deaths
+                .get(&DeathCause::Wall)
+                .unwrap_or(&0)
+                .to_string(),
+        ),
+        (
+            "deaths by self".to_string(),
+            stats
+                .deaths
+                .get(&DeathCause::OwnBody)
+                .unwrap_or(&0)
+                .to_string(),
+        ),
+        (
+            "deaths by hazard".to_string(),
+            stats
+                .deaths
+                .get(&DeathCause::Hazard)
+                .unwrap_or(&0)
+                .to_string(),
+        ),
+        (
+            "deaths by poison".to_string(),
+            stats
+                .deaths
+                .get(&DeathCause::Poison)
+                .unwrap_or(&0)
+                .to_string(),
+        ),
+        ("foods rotted".to_string(), stats.foods_rotted.to_string()),
+        ("boards filled".to_string(), stats.boards_filled.to_string()),
+        (
+            "most used direction".to_string(),
+            stats
+                .most_used_direction()
+                .map(|direction| format!("{:?}", direction).to_lowercase())
+                .unwrap_or_else(|| "-".to_string()),
+        ),
+    ];
+    for kind in FoodKind::ALL {
+        rows.push((
+            format!("{} eaten", kind.icon()),
+            stats.food_eaten.get(&kind).unwrap_or(&0).to_string(),
+        ));
+    }
+    if data.daily.day.is_some() {
+        rows.push((
+            "daily streak (longest)".to_string(),
+            format!("{} ({})", data.daily.streak, data.daily.longest_streak),
+        ));
+    }
+    for difficulty in Difficulty::ALL {
+        if let Some(record) = data.versus_ai.get(&difficulty) {
+            rows.push((
+                format!("vs {} ai (w/l/d)", difficulty.name()),
+                format!("{}/{}/{}", record.wins, record.losses, record.draws),
+            ));
+        }
+    }
+    let rows = rows
+        .into_iter()
+        .map(|(label, value)| format!("<tr><td>{}</td><td>{}</td></tr>", label, value))
+        .collect::<String>();
+    screen.set_inner_html(&format!("<h3>stats</h3><table>{}</table>", rows));
+
+    let onclick = Closure::wrap(Box::new({
+        let screen = screen.clone();
+        move || screen.remove()
+    }) as Box<dyn FnMut()>);
+    screen.set_onclick(onclick.as_ref().dyn_ref());
+    onclick.forget();
+    document
+        .body()
+        .unwrap_throw()
+        .append_child(&screen)
+        .unwrap_throw();
+}